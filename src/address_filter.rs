@@ -0,0 +1,34 @@
+use resol_vbus::{Data, DataSet};
+
+/// Selects the packets to convert by their VBus addresses, e.g. to export only one of several
+/// controllers sharing a logger.
+///
+/// A packet is selected if its source address is one of the `sources` (or there are none) and its
+/// destination address is one of the `destinations` (or there are none).
+#[derive(Debug, Clone, Default)]
+pub struct AddressFilter {
+    /// Only convert packets sent by one of these addresses, all if empty.
+    pub sources: Vec<u16>,
+
+    /// Only convert packets sent to one of these addresses, all if empty.
+    pub destinations: Vec<u16>,
+}
+
+impl AddressFilter {
+    /// Whether the given data is selected.
+    pub fn matches(&self, data: &Data) -> bool {
+        let header = data.as_header();
+        (self.sources.is_empty() || self.sources.contains(&header.source_address))
+            && (self.destinations.is_empty() || self.destinations.contains(&header.destination_address))
+    }
+
+    /// Remove the data that is not selected.
+    pub(crate) fn apply(&self, data_set: DataSet) -> DataSet {
+        if self.sources.is_empty() && self.destinations.is_empty() {
+            data_set
+        } else {
+            let data = data_set.iter().filter(|data| self.matches(data)).cloned().collect();
+            DataSet::from_data(data_set.timestamp, data)
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::{fs::File, io::Write, path::{Path, PathBuf}};
+
+use flate2::{write::GzEncoder, Compression};
+
+use crate::Result;
+
+/// A file that is written to a temporary file next to it first and only renamed into place on
+/// `commit`, so that the file is either absent, complete or unchanged even if interrupted.
+/// Dropping it without committing removes the temporary file.
+pub(crate) struct AtomicFile {
+    filename: PathBuf,
+    tmp_filename: PathBuf,
+    file: Option<File>,
+}
+
+impl AtomicFile {
+    pub(crate) fn create(filename: &Path) -> Result<AtomicFile> {
+        let name = filename.file_name().unwrap_or_default().to_string_lossy();
+        let tmp_filename = filename.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+
+        let file = File::create(&tmp_filename)?;
+
+        Ok(AtomicFile {
+            filename: filename.to_path_buf(),
+            tmp_filename,
+            file: Some(file),
+        })
+    }
+
+    pub(crate) fn commit(mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+            drop(file);
+
+            if let Err(err) = std::fs::rename(&self.tmp_filename, &self.filename) {
+                let _ = std::fs::remove_file(&self.tmp_filename);
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.as_mut().expect("AtomicFile already committed").write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.as_mut().expect("AtomicFile already committed").flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.tmp_filename);
+        }
+    }
+}
+
+/// An `AtomicFile` that is optionally gzip compressed while writing, or a stream like stdout.
+pub(crate) enum OutputFile {
+    Plain(AtomicFile),
+    Gzip(GzEncoder<AtomicFile>),
+
+    /// Written right away, since it cannot be replaced on commit.
+    Stream(Box<dyn Write>),
+}
+
+impl OutputFile {
+    pub(crate) fn create(filename: &Path, gzip: bool) -> Result<OutputFile> {
+        let file = AtomicFile::create(filename)?;
+        if gzip {
+            Ok(OutputFile::Gzip(GzEncoder::new(file, Compression::default())))
+        } else {
+            Ok(OutputFile::Plain(file))
+        }
+    }
+
+    pub(crate) fn commit(self) -> Result<()> {
+        match self {
+            OutputFile::Plain(file) => file.commit(),
+            OutputFile::Gzip(encoder) => encoder.finish()?.commit(),
+            OutputFile::Stream(mut stream) => Ok(stream.flush()?),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Plain(file) => file.write(buf),
+            OutputFile::Gzip(encoder) => encoder.write(buf),
+            OutputFile::Stream(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Gzip(encoder) => encoder.flush(),
+            OutputFile::Stream(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Replace the contents of a file atomically.
+pub(crate) fn write_file_atomically(filename: &Path, contents: &[u8]) -> Result<()> {
+    let mut file = AtomicFile::create(filename)?;
+    file.write_all(contents)?;
+    file.commit()
+}
@@ -0,0 +1,33 @@
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::{atomic::write_file_atomically, Result};
+
+/// The file storing the checksum of a `.vbus` file.
+fn checksum_filename(vbus_filename: &Path) -> PathBuf {
+    let mut filename = vbus_filename.as_os_str().to_owned();
+    filename.push(".sha256");
+    PathBuf::from(filename)
+}
+
+/// Store the SHA-256 checksum of the contents just written to a `.vbus` file in a
+/// `<DATECODE>.vbus.sha256` file next to it, in the format of `sha256sum` so that
+/// `sha256sum -c` can check it as well.
+pub(crate) fn store_checksum(vbus_filename: &Path, contents: &[u8]) -> Result<()> {
+    let name = vbus_filename.file_name().unwrap_or_default().to_string_lossy();
+    let line = format!("{}  {}\n", sha256_hex(contents), name);
+    write_file_atomically(&checksum_filename(vbus_filename), line.as_bytes())
+}
+
+/// Whether the contents of a `.vbus` file match its stored checksum, `None` if there is none,
+/// e.g. because it was downloaded by an older version.
+pub(crate) fn checksum_matches(vbus_filename: &Path, contents: &[u8]) -> Option<bool> {
+    let line = std::fs::read_to_string(checksum_filename(vbus_filename)).ok()?;
+    let expected = line.split_whitespace().next()?;
+    Some(expected.eq_ignore_ascii_case(&sha256_hex(contents)))
+}
+
+fn sha256_hex(contents: &[u8]) -> String {
+    Sha256::digest(contents).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
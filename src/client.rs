@@ -0,0 +1,285 @@
+use std::{future::Future, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_native_tls::{TlsConnector, TlsStream};
+use async_std::net::TcpStream;
+use futures::AsyncReadExt;
+use http_types::{Method, Request, Response, StatusCode};
+use tracing::{debug, info};
+
+use crate::{Error, Host, Result, SyncOptions};
+
+#[derive(Clone)]
+enum Stream {
+    Plain(TcpStream),
+    Tls(async_dup::Arc<async_dup::Mutex<TlsStream<TcpStream>>>),
+}
+
+#[derive(Clone)]
+pub(crate) struct Connection {
+    /// The origin of the host the connection was opened to, see `Host::origin`.
+    origin: String,
+
+    stream: Stream,
+}
+
+impl Connection {
+    async fn open(host: &Host, options: &SyncOptions) -> Result<Connection> {
+        let stream = TcpStream::connect(host.addr()).await?;
+
+        let stream = if host.tls {
+            let connector = TlsConnector::new()
+                .danger_accept_invalid_certs(options.accept_invalid_certs)
+                .danger_accept_invalid_hostnames(options.accept_invalid_certs);
+            let stream = connector.connect(host.name.as_str(), stream).await?;
+            Stream::Tls(async_dup::Arc::new(async_dup::Mutex::new(stream)))
+        } else {
+            Stream::Plain(stream)
+        };
+
+        Ok(Connection {
+            origin: host.origin(),
+            stream,
+        })
+    }
+
+    async fn send(&self, req: Request) -> Result<Response> {
+        let res = match &self.stream {
+            Stream::Plain(stream) => async_h1::connect(stream.clone(), req).await?,
+            Stream::Tls(stream) => async_h1::connect(stream.clone(), req).await?,
+        };
+        Ok(res)
+    }
+}
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The number of redirects followed for a single request.
+const MAX_REDIRECTS: usize = 5;
+
+/// Sends requests to a host, keeping idle connections open for subsequent requests.
+pub(crate) struct Client<'a> {
+    pub(crate) host: &'a Host,
+    pub(crate) options: &'a SyncOptions,
+
+    /// The host all requests were permanently redirected to, if any.
+    redirected_host: Mutex<Option<Arc<Host>>>,
+
+    idle_conns: Mutex<Vec<Connection>>,
+
+    /// The earliest time the next request may be sent if the requests are rate limited.
+    next_request: Mutex<Instant>,
+}
+
+impl<'a> Client<'a> {
+    pub(crate) fn new(host: &'a Host, options: &'a SyncOptions) -> Client<'a> {
+        Client {
+            host,
+            options,
+            redirected_host: Mutex::new(None),
+            idle_conns: Mutex::new(Vec::new()),
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Send a request, retrying with exponential backoff on network errors and 5xx responses.
+    ///
+    /// The connection is returned alongside the response and should be handed back using
+    /// `release` after the response body was consumed completely.
+    pub(crate) async fn send(&self, method: Method, path: &str) -> Result<(Connection, Response)> {
+        self.send_with_headers(method, path, &[]).await
+    }
+
+    /// Like `send`, but adds the given headers to the request.
+    pub(crate) async fn send_with_headers(&self, method: Method, path: &str, headers: &[(&str, String)]) -> Result<(Connection, Response)> {
+        self.send_with_body(method, path, headers, None, true).await
+    }
+
+    /// Like `send_with_headers`, but also sends the given body.
+    ///
+    /// Unless the request is `idempotent`, it is not retried once it was sent, since the server
+    /// may already have processed it before failing or timing out.
+    ///
+    /// Redirects are followed, also to other hosts or from HTTP to HTTPS. Once a request was
+    /// redirected permanently to another host, all subsequent requests are sent there.
+    pub(crate) async fn send_with_body(&self, method: Method, path: &str, headers: &[(&str, String)], body: Option<&[u8]>, idempotent: bool) -> Result<(Connection, Response)> {
+        let mut redirected_host = self.redirected_host.lock().unwrap().clone();
+        let mut path = path.to_string();
+
+        for _ in 0..MAX_REDIRECTS {
+            let host = redirected_host.as_deref().unwrap_or(self.host);
+
+            let (conn, res) = self.send_with_retries(host, method, &path, headers, body, idempotent).await?;
+
+            let location = match res.header("location") {
+                Some(location) if res.status().is_redirection() && res.status() != StatusCode::NotModified => location.as_str().to_string(),
+                _ => return Ok((conn, res)),
+            };
+
+            let url = host.url(&path)?.join(&location)?;
+            debug!("Following redirect of {} {} to {}", method, path, url);
+
+            // The connection cannot be reused without reading the body of the redirect first
+            drop(conn);
+
+            let target = Arc::new(host.redirected(&url)?);
+            let is_permanent = matches!(res.status(), StatusCode::MovedPermanently | StatusCode::PermanentRedirect);
+            if is_permanent && target.origin() != host.origin() {
+                *self.redirected_host.lock().unwrap() = Some(target.clone());
+            }
+
+            redirected_host = Some(target);
+            path = match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            };
+        }
+
+        Err(format!("Too many redirects for {} {}", method, path).into())
+    }
+
+    /// Send a request, retrying with exponential backoff on network errors and 5xx responses, or
+    /// only on failures to send it unless it is `idempotent`.
+    async fn send_with_retries(&self, host: &Host, method: Method, path: &str, headers: &[(&str, String)], body: Option<&[u8]>, idempotent: bool) -> Result<(Connection, Response)> {
+        let mut attempt = 0;
+        loop {
+            let mut sent = false;
+            let result = self.try_send(host, method, path, headers, body, &mut sent).await;
+
+            let reason = match &result {
+                Ok((_, res)) if res.status().is_server_error() => format!("status {}", res.status()),
+                Ok(_) => return result,
+                Err(err) => err.to_string(),
+            };
+
+            if attempt >= self.options.retries || (sent && !idempotent) {
+                return result;
+            }
+
+            let delay = self.options.retry_delay * 2u32.pow(attempt);
+            attempt += 1;
+
+            debug!("Retrying {} {} in {:?} ({}/{}) after {}", method, path, delay, attempt, self.options.retries, reason);
+
+            async_std::task::sleep(delay).await;
+        }
+    }
+
+    /// Wait until the next request may be sent according to `SyncOptions::max_request_rate`.
+    async fn throttle(&self) {
+        let max_request_rate = match self.options.max_request_rate {
+            Some(max_request_rate) if max_request_rate > 0.0 => max_request_rate,
+            _ => return,
+        };
+
+        let delay = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let start = (*next_request).max(now);
+            *next_request = start + Duration::from_secs_f64(1.0 / max_request_rate);
+            start - now
+        };
+        if !delay.is_zero() {
+            async_std::task::sleep(delay).await;
+        }
+    }
+
+    /// Send a request once, setting `sent` once the server may have received it.
+    async fn try_send(&self, host: &Host, method: Method, path: &str, headers: &[(&str, String)], body: Option<&[u8]>, sent: &mut bool) -> Result<(Connection, Response)> {
+        self.throttle().await;
+
+        let what = format!("waiting for response to {} {}", method, path);
+
+        let request = || -> Result<Request> {
+            let mut req = host.request(method, path)?;
+            req.insert_header("User-Agent", self.options.user_agent.as_str());
+            for (name, value) in headers {
+                req.insert_header(*name, value.as_str());
+            }
+            if let Some(body) = body {
+                req.set_body(body.to_vec());
+            }
+            Ok(req)
+        };
+
+        let idle_conn = {
+            let mut idle_conns = self.idle_conns.lock().unwrap();
+            let origin = host.origin();
+            idle_conns.iter().position(|conn| conn.origin == origin).map(|index| idle_conns.swap_remove(index))
+        };
+        if let Some(conn) = idle_conn {
+            let req = request()?;
+            match with_timeout(self.options.timeout, what.clone(), conn.send(req)).await {
+                Ok(res) => {
+                    *sent = true;
+                    return Ok((conn, res));
+                }
+                // Unlike a connection closed while idle, the server may be processing the request
+                Err(err @ Error::Timeout(..)) => {
+                    *sent = true;
+                    return Err(err);
+                }
+                Err(err) => debug!("Reconnecting to {:?} after {}", host.name, err),
+            }
+        }
+
+        let connect_what = format!("connecting to {:?}", host.name);
+        let conn = with_timeout(self.options.timeout, connect_what, Connection::open(host, self.options)).await?;
+
+        let req = request()?;
+        *sent = true;
+        let res = with_timeout(self.options.timeout, what, conn.send(req)).await?;
+        Ok((conn, res))
+    }
+
+    /// Hand back a connection for reuse, unless the server asked to close it.
+    pub(crate) fn release(&self, conn: Connection, res: &Response) {
+        let close = match res.header("connection") {
+            Some(value) => value.as_str().eq_ignore_ascii_case("close"),
+            None => false,
+        };
+        if !close {
+            self.idle_conns.lock().unwrap().push(conn);
+        }
+    }
+}
+
+/// Read a response body in chunks, logging the progress if it takes a while.
+pub(crate) async fn read_body_with_progress(res: &mut Response, options: &SyncOptions, what: &str) -> Result<Vec<u8>> {
+    let expected_len = res.len();
+
+    let mut body = res.take_body();
+    let mut bytes = Vec::with_capacity(expected_len.unwrap_or(0));
+    let mut chunk = vec![0u8; 64 * 1024];
+    let mut last_progress = Instant::now();
+
+    loop {
+        let len = with_timeout(options.timeout, what, async { Ok(body.read(&mut chunk).await?) }).await?;
+        if len == 0 {
+            break;
+        }
+
+        bytes.extend_from_slice(&chunk [..len]);
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+
+            match expected_len {
+                Some(expected_len) if expected_len > 0 => {
+                    let percent = bytes.len() * 100 / expected_len;
+                    info!("Still {}: {} of {} bytes ({}%)", what, bytes.len(), expected_len, percent);
+                }
+                _ => info!("Still {}: {} bytes", what, bytes.len()),
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Wait for `future`, failing with `Error::Timeout` for `what` if it takes longer than `after`.
+pub(crate) async fn with_timeout<T>(after: Duration, what: impl Into<String>, future: impl Future<Output = Result<T>>) -> Result<T> {
+    match async_std::future::timeout(after, future).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::Timeout(what.into(), after)),
+    }
+}
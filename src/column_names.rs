@@ -0,0 +1,45 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{Error, Result};
+
+/// Display names replacing the headers of the columns holding certain fields, e.g. friendlier
+/// ones for a BI tool.
+///
+/// Fields are looked up by their name in the specification, matched case-insensitively. Fields
+/// without a display name keep their usual header, like `Temperatur Sensor 1 [°C]`.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnNames {
+    /// The display names by lowercase field name.
+    names: HashMap<String, String>,
+}
+
+impl ColumnNames {
+    /// Parse a JSON object mapping field names to display names, like
+    /// `{"Temperatur Sensor 1": "collector_temperature"}`.
+    pub fn parse(contents: &str) -> Result<ColumnNames> {
+        let names: HashMap<String, String> = serde_json::from_str(contents).map_err(|err| Error::Parse(Box::new(err)))?;
+        let mut column_names = ColumnNames::default();
+        for (field_name, display_name) in names {
+            column_names.insert(&field_name, &display_name);
+        }
+        Ok(column_names)
+    }
+
+    /// Read a JSON file mapping field names to display names.
+    pub fn from_file(filename: &Path) -> Result<ColumnNames> {
+        ColumnNames::parse(&std::fs::read_to_string(filename)?)
+    }
+
+    /// Use the display name for the column holding the field of the given name.
+    pub fn insert(&mut self, field_name: &str, display_name: &str) {
+        self.names.insert(field_name.to_lowercase(), display_name.to_string());
+    }
+
+    /// The display name of the column holding the field of the given name, if any.
+    pub fn get(&self, field_name: &str) -> Option<&str> {
+        if self.names.is_empty() {
+            return None;
+        }
+        self.names.get(&field_name.to_lowercase()).map(String::as_str)
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+
+use resol_vbus::Language;
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+use crate::{parse_language, Result};
+
+/// The hosts to sync, read from a TOML file.
+///
+/// ```toml
+/// [[hosts]]
+/// host = "d123456789.vbus.io"
+/// timezone = "UTC"
+///
+/// [[hosts]]
+/// host = "192.168.1.50:8080"
+/// output_dir = "/var/lib/vbus"
+/// language = "en"
+/// username = "admin"
+/// password = "admin"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The devices to download from.
+    #[serde(default)]
+    pub hosts: Vec<HostConfig>,
+}
+
+/// A device to download from and the settings that differ from the command line options.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    /// The device, given like on the command line as `[http[s]://][USER[:PASS]@]HOST[:PORT]`.
+    pub host: String,
+
+    /// Directory to create the host directory in.
+    pub output_dir: Option<PathBuf>,
+
+    /// Time zone that determines the days and timestamps in the output files.
+    #[serde(default, deserialize_with = "deserialize_timezone")]
+    pub timezone: Option<chrono_tz::Tz>,
+
+    /// Language of the column names.
+    #[serde(default, deserialize_with = "deserialize_language")]
+    pub language: Option<Language>,
+
+    /// Username for HTTP Basic Auth, unless given as part of the host.
+    pub username: Option<String>,
+
+    /// Password for HTTP Basic Auth, unless given as part of the host.
+    pub password: Option<String>,
+}
+
+impl Config {
+    /// Parse the contents of a config file.
+    pub fn parse(contents: &str) -> Result<Config> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Read a config file.
+    pub fn from_file(filename: &Path) -> Result<Config> {
+        Config::parse(&std::fs::read_to_string(filename)?)
+    }
+}
+
+fn deserialize_timezone<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<chrono_tz::Tz>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map(Some).map_err(D::Error::custom)
+}
+
+fn deserialize_language<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Language>, D::Error> {
+    let code = String::deserialize(deserializer)?;
+    parse_language(&code).map(Some).map_err(D::Error::custom)
+}
@@ -0,0 +1,1365 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs::{create_dir_all, read_dir, File}, io::{Read, Write}, path::{Path, PathBuf}, str::FromStr, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::SystemTime};
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use rayon::prelude::*;
+use resol_vbus::{Data, DataSet, Language, Packet, RecordingReader, Specification};
+use tracing::{debug, debug_span, info, warn};
+
+use crate::{atomic::write_file_atomically, valid_records::{copy_valid_records, ValidRecords}, client::Client, format::CsvFormat, mqtt::MqttClient, webhook::WebhookTarget, resample::Resampler, output::{header_row, ColumnHeaders, CsvWriter, DayWriter, FieldsWriter, Field, InfluxWriter, JsonWriter, MqttWriter, ParquetWriter, SqliteDatabase, SqliteWriter, SummaryWriter, TeeWriter, WebhookWriter}, parse_timestamp_format, AddressFilter, ColumnNames, DateRange, FilenamePattern, DecimalSeparator, Error, FieldFilter, ValueStyle, MqttOptions, WebhookOptions, DEFAULT_TIMESTAMP_FORMAT, OutputFormat, Resample, Result, SourceAddresses, SpecSource};
+
+/// Options controlling how log files are converted.
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    /// The time zone that determines the days and the timestamps in the CSV files.
+    pub timezone: chrono_tz::Tz,
+
+    /// Only convert the days within this range.
+    pub date_range: DateRange,
+
+    /// The decimal separator of numbers, defaulting to the one of the specification language.
+    pub decimal_separator: Option<DecimalSeparator>,
+
+    /// The character between two cells of the CSV files. Cells containing it are quoted.
+    pub delimiter: u8,
+
+    /// The chrono format string of the timestamps in the first column of the CSV files.
+    pub timestamp_format: String,
+
+    /// The directory to write the output files into instead of the one holding the `.vbus`
+    /// files, e.g. because that is mounted read-only.
+    pub output_dir: Option<PathBuf>,
+
+    /// The names of the output files of each day, relative to the directory.
+    pub filename_pattern: FilenamePattern,
+
+    /// Start the CSV files with a UTF-8 byte order mark, so that Excel detects their encoding.
+    pub bom: bool,
+
+    /// The format of the output files.
+    pub output_format: OutputFormat,
+
+    /// Only convert the fields selected by this filter.
+    pub field_filter: FieldFilter,
+
+    /// Write exactly the fields of these names as columns in this order, matched
+    /// case-insensitively, instead of those selected by the field filter. Fields missing from a
+    /// day are written as empty values, and a name given twice selects the next field of that
+    /// name.
+    pub columns: Vec<String>,
+
+    /// Display names replacing the headers of the columns of certain fields.
+    pub column_names: ColumnNames,
+
+    /// The header of the column holding the timestamps, instead of `Datum` or `Date` depending on
+    /// the language.
+    pub timestamp_column: Option<String>,
+
+    /// Whether and how the source address of the packet of each field is shown.
+    pub source_addresses: SourceAddresses,
+
+    /// Only convert the packets of the devices selected by this filter.
+    pub address_filter: AddressFilter,
+
+    /// Whether to write just the values or append their units in the CSV files and MQTT messages.
+    pub value_style: ValueStyle,
+
+    /// Compress the CSV or JSON output files using gzip, appending `.gz` to their names.
+    pub gzip: bool,
+
+    /// Reduce the data sets to at most one per interval.
+    pub resample: Option<Resample>,
+
+    /// What to do with data sets older than one read before them.
+    pub out_of_order: OutOfOrder,
+
+    /// Skip data sets whose selected fields have the same values as the previous one written,
+    /// except for the first data set of each day.
+    pub changes_only: bool,
+
+    /// Omit the fields whose values are the same in all data sets of a day, including those that
+    /// are always empty.
+    pub skip_constant: bool,
+
+    /// Also write the output file of a day without any data set, holding only the header, so
+    /// that days without data can be told apart from days not converted yet.
+    pub write_empty: bool,
+
+    /// Write all days of the date range, which must be bounded, into a single
+    /// `<FROM>-<TO>.<EXT>` file instead of one file per day.
+    pub merge: bool,
+
+    /// Also write a `<DATECODE>_summary.csv` file per day with the minimum, maximum and mean of
+    /// each number.
+    pub summary: bool,
+
+    /// Also write a `<DATECODE>.fields.json` file per output file describing its columns.
+    pub fields_json: bool,
+
+    /// Fail once a day contains more corrupt records than this, instead of skipping them.
+    pub max_corrupt_records: Option<usize>,
+
+    /// Only append the data sets newer than the last row of an existing CSV file, instead of
+    /// writing the whole day again, as long as its columns are unchanged.
+    pub append: bool,
+
+    /// Also publish the data sets of the converted days to this MQTT broker.
+    pub mqtt: Option<MqttOptions>,
+
+    /// Also POST the data sets of the converted days as JSON to this webhook.
+    pub webhook: Option<WebhookOptions>,
+
+    /// Report the periods longer than this without any data set within the converted days.
+    pub gap_threshold: Option<std::time::Duration>,
+
+    /// Also write the gaps found into a `<DATECODE>_gaps.json` file per day.
+    pub gaps_json: bool,
+
+    /// Only log which output files would be written, without writing anything.
+    pub dry_run: bool,
+
+    /// Once set, no further days are converted, e.g. after Ctrl-C was pressed.
+    pub interrupted: Arc<AtomicBool>,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> ConvertOptions {
+        ConvertOptions {
+            timezone: chrono_tz::Europe::Berlin,
+            date_range: DateRange::default(),
+            decimal_separator: None,
+            delimiter: b'\t',
+            timestamp_format: DEFAULT_TIMESTAMP_FORMAT.to_string(),
+            output_dir: None,
+            filename_pattern: FilenamePattern::default(),
+            bom: false,
+            output_format: OutputFormat::Csv,
+            field_filter: FieldFilter::default(),
+            columns: Vec::new(),
+            column_names: ColumnNames::default(),
+            timestamp_column: None,
+            source_addresses: SourceAddresses::default(),
+            address_filter: AddressFilter::default(),
+            value_style: ValueStyle::Raw,
+            gzip: false,
+            resample: None,
+            out_of_order: OutOfOrder::default(),
+            changes_only: false,
+            skip_constant: false,
+            write_empty: false,
+            summary: false,
+            fields_json: false,
+            append: false,
+            max_corrupt_records: None,
+            merge: false,
+            mqtt: None,
+            webhook: None,
+            gap_threshold: None,
+            gaps_json: false,
+            dry_run: false,
+            interrupted: Arc::default(),
+        }
+    }
+}
+
+/// What to do with data sets older than one read before them, e.g. after the clock of a device
+/// jumped backwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfOrder {
+    /// Write them where they were recorded, warning about them.
+    #[default]
+    Warn,
+
+    /// Skip them, warning about them.
+    Drop,
+
+    /// Sort all data sets of a day by their timestamps before writing them, which needs to keep
+    /// the whole day in memory.
+    Sort,
+}
+
+impl FromStr for OutOfOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<OutOfOrder, String> {
+        match s {
+            "warn" => Ok(OutOfOrder::Warn),
+            "drop" => Ok(OutOfOrder::Drop),
+            "sort" => Ok(OutOfOrder::Sort),
+            _ => Err(format!("Unknown out-of-order handling {:?}, expected warn, drop or sort", s)),
+        }
+    }
+}
+
+/// The outcome of converting a directory.
+#[derive(Debug, Default)]
+pub struct ConvertReport {
+    /// The output files that were written.
+    pub converted: Vec<PathBuf>,
+
+    /// Number of days whose output file was already up to date.
+    pub up_to_date: usize,
+
+    /// Number of days that were not written because no data set fell within them.
+    pub empty: usize,
+
+    /// Whether converting was interrupted before all days were converted.
+    pub interrupted: bool,
+
+    /// The gaps found in the converted days, if a threshold was given.
+    pub gaps: Vec<Gap>,
+}
+
+/// A period without data sets, e.g. while the device was offline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+    /// The timestamp of the data set before the gap.
+    pub start: DateTime<Utc>,
+
+    /// The timestamp of the data set after the gap.
+    pub end: DateTime<Utc>,
+}
+
+impl Gap {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// The `YYYYMMDD` datecode a file name starts with, like `20240401.vbus`.
+pub(crate) fn datecode_prefix(filename: &str) -> Option<&str> {
+    // Names may be shorter than a datecode or start with a multi-byte character
+    filename.get(0..8).filter(|prefix| prefix.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Parse a `YYYYMMDD` datecode into the first instant of that day in the given time zone.
+pub(crate) fn parse_datecode<Tz: TimeZone>(datecode_str: &str, tz: &Tz) -> Result<DateTime<Tz>> {
+    let date = NaiveDate::parse_from_str(datecode_str, "%Y%m%d")
+        .map_err(|err| Error::Parse(format!("Invalid datecode {:?}: {}", datecode_str, err).into()))?;
+    start_of_day(date, tz)
+}
+
+/// The first instant of a day in the given time zone.
+///
+/// That is midnight, unless the time zone skips it when switching to daylight saving time (e.g.
+/// `America/Santiago`), in which case it is the first valid time after that.
+fn start_of_day<Tz: TimeZone>(date: NaiveDate, tz: &Tz) -> Result<DateTime<Tz>> {
+    let mut local = date.and_time(NaiveTime::MIN);
+    while local.date() == date {
+        if let Some(dt) = tz.from_local_datetime(&local).earliest() {
+            return Ok(dt);
+        }
+        local += Duration::minutes(15);
+    }
+    Err(format!("Day {} does not exist in the time zone", date).into())
+}
+
+/// The first instant of the day following the one starting at `start_of_day`, which is where
+/// that day ends exclusively. Days are 23 or 25 hours long when switching daylight saving time.
+fn end_of_day<Tz: TimeZone>(start_of_day: &DateTime<Tz>) -> Result<DateTime<Tz>> {
+    let date = start_of_day.naive_local().date();
+    let next_date = date.succ_opt().ok_or_else(|| format!("Day {} has no successor", date))?;
+    self::start_of_day(next_date, &start_of_day.timezone())
+}
+
+/// The first instant of the first local day and the first instant after the last local day of
+/// an output file named `YYYYMMDD` or `YYYYMMDD-YYYYMMDD`.
+fn output_range<Tz: TimeZone>(output_name: &str, tz: &Tz) -> Result<(DateTime<Tz>, DateTime<Tz>)> {
+    let (first, last) = output_name.split_once('-').unwrap_or((output_name, output_name));
+    Ok((parse_datecode(first, tz)?, end_of_day(&parse_datecode(last, tz)?)?))
+}
+
+/// The time a file was last modified, or `None` if it does not exist.
+fn file_modified(filename: &Path) -> Option<SystemTime> {
+    std::fs::metadata(filename).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// The name of the database within the directory when converting to `OutputFormat::Sqlite`.
+const SQLITE_FILENAME: &str = "data.sqlite";
+
+/// What follows the datecode in the names of the files `vbus-sync live` records into, which are
+/// converted together with the log file of the same day but never synced.
+pub(crate) const LIVE_FILE_SUFFIX: &str = ".live.vbus";
+
+/// What follows the name of an output file in the names of its summary, fields and gaps files.
+pub(crate) const SIDECAR_FILE_SUFFIXES: [&str; 3] = [SUMMARY_FILE_SUFFIX, FIELDS_FILE_SUFFIX, GAPS_FILE_SUFFIX];
+
+const SUMMARY_FILE_SUFFIX: &str = "_summary.csv";
+
+const FIELDS_FILE_SUFFIX: &str = ".fields.json";
+
+const GAPS_FILE_SUFFIX: &str = "_gaps.json";
+
+/// The names of the files holding the data of a UTC day, the log file and the live recording.
+fn vbus_rel_filenames(datecode: &str) -> [String; 2] {
+    [format!("{}.vbus", datecode), format!("{}{}", datecode, LIVE_FILE_SUFFIX)]
+}
+
+/// A `<DATECODE>.vbus` or `<DATECODE>.live.vbus` file found in a host directory.
+pub(crate) struct VbusFile {
+    /// The name of the file within the directory.
+    pub(crate) filename: String,
+
+    pub(crate) size: u64,
+
+    pub(crate) modified: SystemTime,
+}
+
+/// Find the `<DATECODE>.vbus` and `<DATECODE>.live.vbus` files in a directory, sorted by name.
+pub(crate) fn scan_vbus_files(dir: &Path) -> Result<Vec<VbusFile>> {
+    let mut vbus_files = Vec::new();
+
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+
+        if !entry.file_type()?.is_file() {
+            // nop
+        } else {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if datecode_prefix(&filename).is_none() {
+                // nop
+            } else if vbus_rel_filenames(&filename [0..8]).contains(&filename) {
+                let metadata = entry.metadata()?;
+                vbus_files.push(VbusFile {
+                    filename,
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                });
+            }
+        }
+    }
+
+    vbus_files.sort_by(|left, right| left.filename.cmp(&right.filename));
+
+    Ok(vbus_files)
+}
+
+/// Convert the `.vbus` files in `dir` into one output file per local day, skipping days whose
+/// output file is newer than all of its `.vbus` files. The output files go into `dir` as well,
+/// unless `options.output_dir` is given.
+///
+/// For `OutputFormat::Sqlite` all days go into a single database instead, skipping the days that
+/// end before the latest data set already stored.
+pub fn convert(dir: &Path, spec: &SpecSource, options: &ConvertOptions) -> Result<ConvertReport> {
+    let output_extension = match (options.output_format, options.gzip) {
+        (OutputFormat::Csv | OutputFormat::Json, true) => format!(".{}.gz", options.output_format.extension()),
+        (_, true) => return Err("Only CSV and JSON output files can be compressed".into()),
+        (_, false) => format!(".{}", options.output_format.extension()),
+    };
+
+    parse_timestamp_format(&options.timestamp_format).map_err(|err| Error::Parse(err.into()))?;
+
+    if options.append {
+        if options.output_format != OutputFormat::Csv || options.gzip {
+            return Err("Only uncompressed CSV files can be appended to".into());
+        }
+        // These depend on all data sets of a day
+        if options.merge || options.resample.is_some() || options.skip_constant || options.summary {
+            return Err("Appending cannot be combined with merging, resampling, skipping constant fields or summaries".into());
+        }
+    }
+
+    // Nothing was downloaded into a new directory during a dry run
+    if options.dry_run && !dir.is_dir() {
+        return Ok(ConvertReport::default());
+    }
+
+    let output_dir = match &options.output_dir {
+        Some(output_dir) => {
+            if !options.dry_run {
+                create_dir_all(output_dir)?;
+            }
+            output_dir.as_path()
+        }
+        None => dir,
+    };
+
+    let vbus_files = scan_vbus_files(dir)?;
+    let all_vbus_filenames = vbus_files.iter().map(|vbus_file| vbus_file.filename.clone()).collect::<Vec<_>>();
+    let vbus_file_modified_by_rel_filename = vbus_files.into_iter().map(|vbus_file| (vbus_file.filename, vbus_file.modified)).collect::<HashMap<_, _>>();
+
+    let tz = options.timezone;
+
+    let host = match dir.file_name() {
+        Some(host) => host.to_string_lossy().to_string(),
+        None => String::new(),
+    };
+
+    let is_sqlite = options.output_format == OutputFormat::Sqlite;
+    let database = match (is_sqlite, options.dry_run) {
+        (true, false) => Some(SqliteDatabase::open(&output_dir.join(SQLITE_FILENAME))?),
+        (true, true) => SqliteDatabase::open_read_only(&output_dir.join(SQLITE_FILENAME))?,
+        (false, _) => None,
+    };
+    let latest_stored = match &database {
+        Some(database) => database.latest_timestamp()?,
+        None => None,
+    };
+
+    let mut local_to_utc_datecodes = BTreeMap::new();
+
+    for vbus_filename in &all_vbus_filenames {
+        let datecode_str_utc = vbus_filename [0..8].to_string();
+
+        let start_of_day_utc = match parse_datecode(&datecode_str_utc, &Utc) {
+            Ok(start_of_day_utc) => start_of_day_utc,
+            Err(err) => {
+                warn!("Skipping {:?}: {}", vbus_filename, err);
+                continue;
+            }
+        };
+        // VBus timestamps have millisecond precision
+        let last_of_day_utc = end_of_day(&start_of_day_utc)? - Duration::milliseconds(1);
+
+        let start_of_day_local = start_of_day_utc.with_timezone(&tz);
+        let end_of_day_local = last_of_day_utc.with_timezone(&tz);
+
+        let start_of_day_local_datecode = start_of_day_local.format("%Y%m%d").to_string();
+        let end_of_day_local_datecode = end_of_day_local.format("%Y%m%d").to_string();
+
+        if !local_to_utc_datecodes.contains_key(&start_of_day_local_datecode) {
+            local_to_utc_datecodes.insert(start_of_day_local_datecode.clone(), Vec::new());
+        }
+        local_to_utc_datecodes.get_mut(&start_of_day_local_datecode).unwrap().push(datecode_str_utc.clone());
+
+        if !local_to_utc_datecodes.contains_key(&end_of_day_local_datecode) {
+            local_to_utc_datecodes.insert(end_of_day_local_datecode.clone(), Vec::new());
+        }
+        local_to_utc_datecodes.get_mut(&end_of_day_local_datecode).unwrap().push(datecode_str_utc.clone());
+    }
+
+    let mut pending_conversions = Vec::new();
+    let mut up_to_date = 0;
+
+    if options.merge {
+        let (from, to) = match (options.date_range.from, options.date_range.to, is_sqlite) {
+            (_, _, true) => return Err("Days cannot be merged into a SQLite database".into()),
+            (Some(from), Some(to), false) => (from, to),
+            _ => return Err("Merging days requires both ends of the date range".into()),
+        };
+
+        let output_name = format!("{}-{}", from.format("%Y%m%d"), to.format("%Y%m%d"));
+        let output_filename = output_dir.join(format!("{}{}", output_name, output_extension));
+
+        let output_modified = file_modified(&output_filename);
+
+        let mut vbus_filenames = Vec::new();
+        let mut needs_conversion = output_modified.is_none();
+        for (output_datecode, vbus_datecodes) in &local_to_utc_datecodes {
+            if !options.date_range.contains_datecode(output_datecode) {
+                continue;
+            }
+            for rel_vbus_filename in vbus_datecodes.iter().flat_map(|vbus_datecode| vbus_rel_filenames(vbus_datecode)) {
+                let vbus_filename = dir.join(&rel_vbus_filename);
+                if let Some(vbus_modified) = vbus_file_modified_by_rel_filename.get(&rel_vbus_filename) {
+                    if !vbus_filenames.contains(&vbus_filename) {
+                        vbus_filenames.push(vbus_filename);
+                    }
+                    if output_modified.is_some_and(|output_modified| *vbus_modified > output_modified) {
+                        needs_conversion = true;
+                    }
+                }
+            }
+        }
+        vbus_filenames.sort();
+
+        if needs_conversion {
+            pending_conversions.push(PendingConversion {
+                summary_filename: options.summary.then(|| output_dir.join(format!("{}{}", output_name, SUMMARY_FILE_SUFFIX))),
+                fields_filename: options.fields_json.then(|| output_dir.join(format!("{}{}", output_name, FIELDS_FILE_SUFFIX))),
+                gaps_filename: options.gaps_json.then(|| output_dir.join(format!("{}{}", output_name, GAPS_FILE_SUFFIX))),
+                datecode: output_name,
+                vbus_filenames,
+                output_filename,
+            });
+        } else {
+            up_to_date += 1;
+        }
+
+        // Skip the conversion of single days below
+        local_to_utc_datecodes.clear();
+    }
+
+    for (output_datecode, mut vbus_datecodes) in local_to_utc_datecodes {
+        if !options.date_range.contains_datecode(&output_datecode) {
+            continue;
+        }
+
+        let output_name = options.filename_pattern.expand(parse_datecode(&output_datecode, &tz)?.date_naive(), &host);
+        let rel_output_filename = format!("{}{}", &output_name, output_extension);
+
+        // Both ends of a UTC day fall on the same local day in time zones without offset, and a
+        // UTC day may have been both synced and recorded live
+        vbus_datecodes.sort();
+        vbus_datecodes.dedup();
+
+        let output_modified = file_modified(&output_dir.join(&rel_output_filename));
+
+        let mut vbus_filenames = Vec::new();
+        let mut needs_conversion = output_modified.is_none();
+        for rel_vbus_filename in vbus_datecodes.iter().flat_map(|vbus_datecode| vbus_rel_filenames(vbus_datecode)) {
+            if let Some(vbus_modified) = vbus_file_modified_by_rel_filename.get(&rel_vbus_filename) {
+                vbus_filenames.push(dir.join(rel_vbus_filename));
+
+                if !needs_conversion && *vbus_modified > output_modified.unwrap() {
+                    needs_conversion = true;
+                }
+            }
+        }
+
+        let output_filename = if is_sqlite {
+            let end_of_day_utc = end_of_day(&parse_datecode(&output_datecode, &tz)?)?.with_timezone(&Utc);
+            needs_conversion = latest_stored.is_none_or(|latest_stored| end_of_day_utc > latest_stored);
+            output_dir.join(SQLITE_FILENAME)
+        } else {
+            output_dir.join(rel_output_filename)
+        };
+
+        if needs_conversion {
+            pending_conversions.push(PendingConversion {
+                datecode: output_datecode,
+                vbus_filenames,
+                output_filename,
+                summary_filename: options.summary.then(|| output_dir.join(format!("{}{}", output_name, SUMMARY_FILE_SUFFIX))),
+                fields_filename: options.fields_json.then(|| output_dir.join(format!("{}{}", output_name, FIELDS_FILE_SUFFIX))),
+                gaps_filename: options.gaps_json.then(|| output_dir.join(format!("{}{}", output_name, GAPS_FILE_SUFFIX))),
+            });
+        } else {
+            up_to_date += 1;
+        }
+    }
+
+    if options.dry_run {
+        for pending in &pending_conversions {
+            info!("Would convert {} into {:?}", pending.datecode, pending.output_filename);
+        }
+
+        return Ok(ConvertReport {
+            up_to_date,
+            ..ConvertReport::default()
+        });
+    }
+
+    let mqtt_client = match &options.mqtt {
+        Some(mqtt_options) if !pending_conversions.is_empty() => Some(Mutex::new(async_std::task::block_on(MqttClient::connect(mqtt_options))?)),
+        _ => None,
+    };
+
+    let column_packets = column_packets(spec, &options.columns)?;
+
+    let webhook_target = match &options.webhook {
+        Some(webhook_options) => Some(WebhookTarget::parse(webhook_options)?),
+        None => None,
+    };
+    let webhook_client = webhook_target.as_ref().map(|target| Client::new(&target.host, &target.sync_options));
+
+    // Hand each thread a contiguous run of days, so that the topology hint of the previous day
+    // stays useful within each run
+    let chunk_size = pending_conversions.len().div_ceil(rayon::current_num_threads());
+
+    let chunk_reports = pending_conversions.par_chunks(chunk_size.max(1)).map(|chunk| -> Result<ConvertReport> {
+        let converter = DayConverter {
+            database: database.as_ref(),
+            mqtt: match (&options.mqtt, &mqtt_client) {
+                (Some(mqtt_options), Some(mqtt_client)) => Some((mqtt_client, mqtt_options.base_topic.as_str())),
+                _ => None,
+            },
+            webhook: match (&options.webhook, &webhook_target, &webhook_client) {
+                (Some(webhook_options), Some(webhook_target), Some(webhook_client)) => Some((webhook_client, webhook_target.path.as_str(), webhook_options)),
+                _ => None,
+            },
+            ..DayConverter::new(spec, options, &column_packets, &host)?
+        };
+
+        let mut last_layout = None;
+        let mut report = ConvertReport::default();
+
+        for pending in chunk {
+            if options.interrupted.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let _span = debug_span!("convert", day = %pending.datecode).entered();
+
+            let (layout, written) = converter.convert_day(pending, last_layout.take(), &mut report.gaps)?;
+            if written {
+                report.converted.push(pending.output_filename.clone());
+            } else {
+                report.empty += 1;
+            }
+            last_layout = Some(layout);
+        }
+
+        Ok(report)
+    }).collect::<Result<Vec<_>>>()?;
+
+    if let Some(mqtt_client) = mqtt_client {
+        async_std::task::block_on(mqtt_client.into_inner().unwrap().disconnect())?;
+    }
+
+    let mut report = ConvertReport {
+        up_to_date,
+        ..ConvertReport::default()
+    };
+    for chunk_report in chunk_reports {
+        report.converted.extend(chunk_report.converted);
+        report.empty += chunk_report.empty;
+        report.gaps.extend(chunk_report.gaps);
+    }
+    report.interrupted = report.converted.len() + report.empty < pending_conversions.len();
+    // All days share the same database
+    report.converted.dedup();
+
+    Ok(report)
+}
+
+/// Convert the contents of `.vbus` files read from `input`, e.g. piped into stdin, into CSV
+/// written to `output`, without reading or writing any files.
+///
+/// The data sets within the date range go into a single CSV file like when merging, with the
+/// options applying to its contents as usual. Options about other files, like `summary`, `gzip`
+/// or `mqtt`, are ignored. The columns cannot be changed once written, so `skip_constant` is not
+/// supported. Returns whether any data set was written.
+pub fn convert_stream(input: &mut dyn Read, output: Box<dyn Write>, spec: &SpecSource, options: &ConvertOptions) -> Result<bool> {
+    write_stream(StreamInput::Reader(input), output, spec, options)
+}
+
+/// Convert the `.vbus` files in `dir` covering the date range into CSV written to `output`, like
+/// `convert_stream` does, regardless of any output files written before.
+pub fn convert_dir_to_stream(dir: &Path, output: Box<dyn Write>, spec: &SpecSource, options: &ConvertOptions) -> Result<bool> {
+    // The local days of the range may start on the UTC day before or end on the one after
+    let utc_date_range = options.date_range.widened(1);
+
+    let filenames = scan_vbus_files(dir)?.into_iter()
+        .filter(|vbus_file| utc_date_range.contains_datecode(&vbus_file.filename [0..8]))
+        .map(|vbus_file| dir.join(vbus_file.filename))
+        .collect();
+
+    write_stream(StreamInput::Files(filenames), output, spec, options)
+}
+
+/// The `.vbus` data written to a stream by `write_stream`.
+enum StreamInput<'i> {
+    /// A stream that can only be read once, so its valid records are kept in memory.
+    Reader(&'i mut dyn Read),
+
+    /// Files that are read again for each pass over them.
+    Files(Vec<PathBuf>),
+}
+
+/// Write the data sets within the date range of `.vbus` data into a single CSV file written to
+/// `output`.
+fn write_stream(input: StreamInput<'_>, output: Box<dyn Write>, spec: &SpecSource, options: &ConvertOptions) -> Result<bool> {
+    if options.output_format != OutputFormat::Csv {
+        return Err("Only CSV can be written to a stream".into());
+    }
+    if options.skip_constant {
+        return Err("Skipping constant fields is not supported when writing to a stream".into());
+    }
+    parse_timestamp_format(&options.timestamp_format).map_err(|err| Error::Parse(err.into()))?;
+
+    let column_packets = column_packets(spec, &options.columns)?;
+    let converter = DayConverter::new(spec, options, &column_packets, "")?;
+
+    let mut bytes = Vec::new();
+    let filenames;
+    let recording = match input {
+        StreamInput::Reader(input) => {
+            let mut corrupt_records = 0;
+            copy_valid_records(input, &mut bytes, |offset, reason| converter.skip_corrupt_record(&"stdin", offset, reason, &mut corrupt_records))?;
+            Recording::Bytes(&bytes)
+        }
+        StreamInput::Files(input_filenames) => {
+            filenames = input_filenames;
+            converter.check_vbus_files(&filenames)?;
+            Recording::Files(&filenames)
+        }
+    };
+
+    let tz = options.timezone;
+    let start_utc = match options.date_range.from {
+        Some(from) => start_of_day(from, &tz)?.with_timezone(&Utc),
+        None => DateTime::<Utc>::MIN_UTC,
+    };
+    let end_utc = match options.date_range.to {
+        Some(to) => end_of_day(&start_of_day(to, &tz)?)?.with_timezone(&Utc),
+        None => DateTime::<Utc>::MAX_UTC,
+    };
+
+    // Read the exact topology, since the header cannot be replaced afterwards
+    let mut rr = recording.reader()?;
+    rr.set_min_max_timestamps(Some(start_utc), Some(end_utc));
+    let layout = Layout {
+        topo_data_set: converter.address_filter.apply(rr.read_topology_data_set()?),
+        varying_columns: None,
+    };
+
+    let output = Box::new(CsvWriter::to_stream(output, converter.csv_format, converter.headers, &options.timestamp_format, spec.language(), tz)?);
+    let (output, _, contains_data_lines, out_of_order) = converter.write_day(output, recording, start_utc, end_utc, &layout, None)?;
+    output.commit()?;
+
+    converter.report_out_of_order("stream", out_of_order);
+
+    Ok(contains_data_lines)
+}
+
+/// An empty packet containing the field of each of the given columns, warning about the names
+/// that are not in the specification.
+fn column_packets(spec: &SpecSource, columns: &[String]) -> Result<Vec<Option<Packet>>> {
+    let column_packets = spec.empty_packets_with_fields(columns)?;
+    for (name, _) in columns.iter().zip(&column_packets).filter(|(_, packet)| packet.is_none()) {
+        warn!("Column {:?} is not a field of the VBus specification, skipping it", name);
+    }
+    Ok(column_packets)
+}
+
+/// A day, or a range of days when merging, whose output file needs to be written.
+struct PendingConversion {
+    /// The day as `YYYYMMDD`, or the range of days as `YYYYMMDD-YYYYMMDD`.
+    datecode: String,
+
+    /// The `.vbus` files containing the data sets of those days.
+    vbus_filenames: Vec<PathBuf>,
+
+    output_filename: PathBuf,
+
+    /// The file to additionally write the summary to, if enabled.
+    summary_filename: Option<PathBuf>,
+
+    /// The file to additionally describe the columns in, if enabled.
+    fields_filename: Option<PathBuf>,
+
+    /// The file to additionally write the gaps to, if enabled.
+    gaps_filename: Option<PathBuf>,
+}
+
+/// Converts days one after another, owning a `Specification` since it cannot be shared between
+/// threads.
+struct DayConverter<'a> {
+    spec: Specification,
+    language: Language,
+    tz: chrono_tz::Tz,
+    output_format: OutputFormat,
+    gzip: bool,
+    resample: Option<Resample>,
+    out_of_order: OutOfOrder,
+    changes_only: bool,
+    skip_constant: bool,
+    write_empty: bool,
+    append: bool,
+    max_corrupt_records: Option<usize>,
+    gap_threshold: Option<Duration>,
+
+    field_filter: &'a FieldFilter,
+
+    /// The lowercase names of the fields written as columns in this order, all selected by the
+    /// field filter if empty.
+    columns: Vec<String>,
+
+    /// An empty packet containing the field of each column, if known.
+    column_packets: &'a [Option<Packet>],
+
+    headers: ColumnHeaders<'a>,
+
+    address_filter: &'a AddressFilter,
+    csv_format: CsvFormat,
+    timestamp_format: &'a str,
+    database: Option<&'a SqliteDatabase>,
+
+    /// The name of the host directory, used to tag InfluxDB points.
+    host: &'a str,
+
+    /// The MQTT connection and base topic to additionally publish the data sets to.
+    mqtt: Option<(&'a Mutex<MqttClient>, &'a str)>,
+
+    /// The HTTP client and path of the webhook to additionally POST the data sets to.
+    webhook: Option<(&'a Client<'a>, &'a str, &'a WebhookOptions)>,
+}
+
+impl<'a> DayConverter<'a> {
+    /// A converter for the given options, writing only the output files themselves.
+    fn new(spec: &SpecSource, options: &'a ConvertOptions, column_packets: &'a [Option<Packet>], host: &'a str) -> Result<DayConverter<'a>> {
+        Ok(DayConverter {
+            spec: spec.load()?,
+            language: spec.language(),
+            tz: options.timezone,
+            output_format: options.output_format,
+            gzip: options.gzip,
+            resample: options.resample,
+            out_of_order: options.out_of_order,
+            changes_only: options.changes_only,
+            skip_constant: options.skip_constant,
+            write_empty: options.write_empty,
+            append: options.append,
+            max_corrupt_records: options.max_corrupt_records,
+            gap_threshold: options.gap_threshold.map(|threshold| Duration::milliseconds(threshold.as_millis() as i64)),
+            field_filter: &options.field_filter,
+            columns: options.columns.iter().map(|name| name.to_lowercase()).collect(),
+            column_packets,
+            headers: ColumnHeaders {
+                names: &options.column_names,
+                timestamp: options.timestamp_column.as_deref(),
+                source_addresses: options.source_addresses,
+            },
+            address_filter: &options.address_filter,
+            csv_format: CsvFormat::new(spec.language(), options.decimal_separator, options.delimiter, options.value_style, options.bom),
+            timestamp_format: &options.timestamp_format,
+            database: None,
+            host,
+            mqtt: None,
+            webhook: None,
+        })
+    }
+
+    /// Convert the `.vbus` files of a single local day, or a range of days when merging, into
+    /// its output file.
+    ///
+    /// Returns the layout of the output file, to be passed as `layout_hint` for the next day, and
+    /// whether the output file was written at all. The gaps found are appended to `gaps`.
+    fn convert_day(&self, pending: &PendingConversion, layout_hint: Option<Layout>, gaps: &mut Vec<Gap>) -> Result<(Layout, bool)> {
+        debug!("Converting {:?} into {:?}...", pending.vbus_filenames, pending.output_filename);
+
+        let (start_of_day_local, end_of_day_local) = output_range(&pending.datecode, &self.tz)?;
+
+        let start_of_day_utc = start_of_day_local.with_timezone(&Utc);
+        let end_of_day_utc = end_of_day_local.with_timezone(&Utc);
+
+        self.check_vbus_files(&pending.vbus_filenames)?;
+        let recording = Recording::Files(&pending.vbus_filenames);
+
+        if self.append {
+            if let Some(existing) = read_existing_csv(&pending.output_filename, self.csv_format.delimiter())? {
+                if let Some(layout) = self.append_day(pending, recording, start_of_day_utc, end_of_day_utc, &existing)? {
+                    self.report_gaps(pending, recording, start_of_day_utc, end_of_day_utc, gaps)?;
+                    return Ok((layout, true));
+                }
+            }
+        }
+
+        // Fields rarely change from day to day, so optimistically assume the layout of the
+        // previous day and only convert a second time if the data sets proved otherwise
+        let mut layout = match layout_hint {
+            Some(layout) => layout,
+            None => {
+                let mut rr = recording.reader()?;
+                rr.set_min_max_timestamps(Some(start_of_day_utc), Some(end_of_day_utc));
+                Layout {
+                    topo_data_set: self.address_filter.apply(rr.read_topology_data_set()?),
+                    varying_columns: None,
+                }
+            }
+        };
+
+        loop {
+            let output = self.create_writer(pending, None)?;
+            let (output, seen_layout, contains_data_lines, out_of_order) = self.write_day(output, recording, start_of_day_utc, end_of_day_utc, &layout, None)?;
+
+            if !is_same_topology(&layout.topo_data_set, &seen_layout.topo_data_set) {
+                debug!("    Converting again because fields differ from the assumed ones");
+                layout = Layout {
+                    topo_data_set: seen_layout.topo_data_set,
+                    varying_columns: None,
+                };
+                continue;
+            }
+
+            if self.skip_constant && contains_data_lines && layout.varying_columns != seen_layout.varying_columns {
+                debug!("    Converting again because constant fields differ from the assumed ones");
+                layout = seen_layout;
+                continue;
+            }
+
+            let written = contains_data_lines || self.write_empty;
+            if written {
+                output.commit()?;
+
+                self.report_out_of_order(&pending.datecode, out_of_order);
+                self.report_gaps(pending, recording, start_of_day_utc, end_of_day_utc, gaps)?;
+            } else {
+                debug!("    Skipping because output would be empty");
+            }
+
+            return Ok((layout, written));
+        }
+    }
+
+    /// Append the data sets newer than the last row of an existing CSV file to it.
+    ///
+    /// Returns the layout of the output file, or `None` if it needs to be written from scratch
+    /// instead, e.g. because its columns changed.
+    fn append_day(&self, pending: &PendingConversion, recording: Recording<'_>, start_utc: DateTime<Utc>, end_utc: DateTime<Utc>, existing: &ExistingCsv) -> Result<Option<Layout>> {
+        // Read the exact topology, since the header cannot be replaced afterwards
+        let mut rr = recording.reader()?;
+        rr.set_min_max_timestamps(Some(start_utc), Some(end_utc));
+        let layout = Layout {
+            topo_data_set: self.address_filter.apply(rr.read_topology_data_set()?),
+            varying_columns: None,
+        };
+
+        let header = header_row(&self.written_fields(&self.with_missing_columns(&layout.topo_data_set), None), self.headers, self.language);
+        if header != existing.header {
+            debug!("    Converting from scratch because fields differ from the existing ones");
+            return Ok(None);
+        }
+
+        let start_utc = match &existing.last_timestamp {
+            Some(last_timestamp) => match self.parse_timestamp(last_timestamp) {
+                Some(last_timestamp) => last_timestamp.max(start_utc),
+                None => {
+                    debug!("    Converting from scratch because the last timestamp {:?} is ambiguous", last_timestamp);
+                    return Ok(None);
+                }
+            },
+            None => start_utc,
+        };
+
+        debug!("    Appending data sets since {}", start_utc);
+
+        let output = self.create_writer(pending, Some(existing))?;
+        let (output, _, _, out_of_order) = self.write_day(output, recording, start_utc, end_utc, &layout, Some(existing))?;
+        output.commit()?;
+
+        self.report_out_of_order(&pending.datecode, out_of_order);
+
+        Ok(Some(layout))
+    }
+
+    /// Parse a timestamp written using the timestamp format, which is ambiguous without offset
+    /// while the clocks are turned back.
+    fn parse_timestamp(&self, timestamp: &str) -> Option<DateTime<Utc>> {
+        if let Ok(timestamp) = DateTime::parse_from_str(timestamp, self.timestamp_format) {
+            return Some(timestamp.with_timezone(&Utc));
+        }
+        let timestamp = NaiveDateTime::parse_from_str(timestamp, self.timestamp_format).ok()?;
+        self.tz.from_local_datetime(&timestamp).single().map(|timestamp| timestamp.with_timezone(&Utc))
+    }
+
+    /// Check the records of the given `.vbus` files, warning about the corrupt ones that are
+    /// skipped when reading them.
+    ///
+    /// Fails if there are more corrupt records than allowed.
+    fn check_vbus_files(&self, filenames: &[PathBuf]) -> Result<()> {
+        // Checking the records of a big day takes a while, so check all of its files at once
+        let files = filenames.par_iter().map(|filename| -> Result<_> {
+            let mut corrupt = Vec::new();
+            copy_valid_records(File::open(filename)?, &mut std::io::sink(), |offset, reason| {
+                corrupt.push((offset, reason.to_string()));
+                Ok(())
+            })?;
+            Ok(corrupt)
+        }).collect::<Result<Vec<_>>>()?;
+
+        let mut corrupt_records = 0;
+        for (filename, corrupt) in filenames.iter().zip(files) {
+            for (offset, reason) in corrupt {
+                self.skip_corrupt_record(filename, offset, &reason, &mut corrupt_records)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a corrupt record and warn about skipping it, failing if there are more than allowed.
+    fn skip_corrupt_record(&self, source: &dyn std::fmt::Debug, offset: usize, reason: &str, corrupt_records: &mut usize) -> Result<()> {
+        *corrupt_records += 1;
+        if self.max_corrupt_records.is_some_and(|max_corrupt_records| *corrupt_records > max_corrupt_records) {
+            return Err(format!("Corrupt record in {:?} at offset {}: {}", source, offset, reason).into());
+        }
+        warn!("Skipping corrupt record in {:?} at offset {}: {}", source, offset, reason);
+        Ok(())
+    }
+
+    /// Warn about the data sets of a day that were older than one read before them.
+    fn report_out_of_order(&self, datecode: &str, out_of_order: usize) {
+        if out_of_order > 0 {
+            let action = match self.out_of_order {
+                OutOfOrder::Drop => "skipped",
+                OutOfOrder::Warn | OutOfOrder::Sort => "written out of order",
+            };
+            warn!("{}: {} data sets older than one recorded before them were {}", datecode, out_of_order, action);
+        }
+    }
+
+    /// Find and log the gaps within a day if enabled, also writing them to its gaps file.
+    fn report_gaps(&self, pending: &PendingConversion, recording: Recording<'_>, start_utc: DateTime<Utc>, end_utc: DateTime<Utc>, gaps: &mut Vec<Gap>) -> Result<()> {
+        if let Some(gap_threshold) = self.gap_threshold {
+            let day_gaps = self.find_gaps(recording, start_utc, end_utc, gap_threshold)?;
+            if let Some(gaps_filename) = &pending.gaps_filename {
+                self.write_gaps(gaps_filename, &day_gaps)?;
+            }
+            gaps.extend(day_gaps);
+        }
+        Ok(())
+    }
+
+    /// Find the periods longer than `threshold` between consecutive data sets.
+    fn find_gaps(&self, recording: Recording<'_>, start_utc: DateTime<Utc>, end_utc: DateTime<Utc>, threshold: Duration) -> Result<Vec<Gap>> {
+        let mut rr = recording.reader()?;
+        rr.set_min_max_timestamps(Some(start_utc), Some(end_utc));
+
+        let mut gaps = Vec::new();
+        let mut last_timestamp = None;
+        while let Some(rr_data_set) = rr.read_data_set()? {
+            // Data sets that only contained packets of other devices do not count
+            let was_empty = rr_data_set.is_empty();
+            let rr_data_set = self.address_filter.apply(rr_data_set);
+            if rr_data_set.is_empty() && !was_empty {
+                continue;
+            }
+
+            if let Some(last_timestamp) = last_timestamp {
+                let gap = Gap {
+                    start: last_timestamp,
+                    end: rr_data_set.timestamp,
+                };
+                if gap.duration() > threshold {
+                    warn!("No data sets from {} to {} ({} s)", gap.start.with_timezone(&self.tz), gap.end.with_timezone(&self.tz), gap.duration().num_seconds());
+                    gaps.push(gap);
+                }
+            }
+            last_timestamp = Some(rr_data_set.timestamp);
+        }
+
+        Ok(gaps)
+    }
+
+    /// Write gaps as a JSON array of objects with their `start`, `end` and `duration` in seconds.
+    fn write_gaps(&self, filename: &Path, gaps: &[Gap]) -> Result<()> {
+        let gaps = gaps.iter().map(|gap| serde_json::json!({
+            "start": gap.start.with_timezone(&self.tz).to_rfc3339(),
+            "end": gap.end.with_timezone(&self.tz).to_rfc3339(),
+            "duration": gap.duration().num_seconds(),
+        })).collect::<Vec<_>>();
+
+        let contents = serde_json::to_vec_pretty(&gaps).map_err(std::io::Error::from)?;
+        write_file_atomically(filename, &contents)
+    }
+
+    fn create_writer(&self, pending: &PendingConversion, existing: Option<&ExistingCsv>) -> Result<Box<dyn DayWriter + 'a>> {
+        if let Some(parent) = pending.output_filename.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let mut writers: Vec<Box<dyn DayWriter + 'a>> = Vec::new();
+        if let Some((mqtt_client, base_topic)) = self.mqtt {
+            // Publish first, so that a day is published again if that fails
+            writers.push(Box::new(MqttWriter::new(mqtt_client, base_topic, self.host, self.csv_format)));
+        }
+        if let Some((webhook_client, path, webhook_options)) = self.webhook {
+            writers.push(Box::new(WebhookWriter::new(webhook_client, path, &webhook_options.headers, webhook_options.batch, self.host, &pending.datecode, self.tz)));
+        }
+        writers.push(self.create_output_writer(&pending.output_filename, existing)?);
+        if let Some(summary_filename) = &pending.summary_filename {
+            writers.push(Box::new(SummaryWriter::create(summary_filename, self.csv_format, self.headers, self.language, self.tz)?));
+        }
+        if let Some(fields_filename) = &pending.fields_filename {
+            writers.push(Box::new(FieldsWriter::create(fields_filename, self.headers)?));
+        }
+
+        Ok(if writers.len() == 1 {
+            writers.pop().unwrap()
+        } else {
+            Box::new(TeeWriter::new(writers))
+        })
+    }
+
+    fn create_output_writer(&self, output_filename: &Path, existing: Option<&ExistingCsv>) -> Result<Box<dyn DayWriter + 'a>> {
+        if let Some(existing) = existing {
+            return Ok(Box::new(CsvWriter::append(output_filename, &existing.contents, self.csv_format, self.headers, self.timestamp_format, self.language, self.tz)?));
+        }
+
+        Ok(match (self.output_format, self.database) {
+            (OutputFormat::Sqlite, Some(database)) => Box::new(SqliteWriter::new(database)),
+            (OutputFormat::Sqlite, None) => return Err("No SQLite database opened".into()),
+            (OutputFormat::Csv, _) => Box::new(CsvWriter::create(output_filename, self.gzip, self.csv_format, self.headers, self.timestamp_format, self.language, self.tz)?),
+            (OutputFormat::Json, _) => Box::new(JsonWriter::create(output_filename, self.gzip, self.tz)?),
+            (OutputFormat::Influx, _) => Box::new(InfluxWriter::create(output_filename, self.host)?),
+            (OutputFormat::Parquet, _) => Box::new(ParquetWriter::create(output_filename, self.language, self.headers)?),
+        })
+    }
+
+    /// The fields of a data set selected by the field filter, or those named as columns in their
+    /// order.
+    fn selected_fields<'d>(&'d self, data_set: &'d DataSet) -> Vec<Field<'d>> {
+        let fields = self.spec.fields_in_data_set(data_set);
+        if self.columns.is_empty() {
+            return fields.filter(|field| self.field_filter.matches(&field.field_spec().name)).collect();
+        }
+
+        let mut fields = fields.map(|field| (field.field_spec().name.to_lowercase(), Some(field))).collect::<Vec<_>>();
+        self.columns.iter().filter_map(|column| {
+            let index = fields.iter().position(|(name, field)| name == column && field.is_some())?;
+            fields [index].1.take()
+        }).collect()
+    }
+
+    /// The packets of a topology, plus an empty packet for each column without a field in them,
+    /// so that those are written as empty values.
+    fn with_missing_columns(&self, topo_data_set: &DataSet) -> DataSet {
+        let mut data_set = topo_data_set.clone();
+        let names = self.spec.fields_in_data_set(topo_data_set).map(|field| field.field_spec().name.to_lowercase()).collect::<HashSet<_>>();
+        for (column, packet) in self.columns.iter().zip(self.column_packets) {
+            if let Some(packet) = packet.as_ref().filter(|_| !names.contains(column)) {
+                data_set.add_data(Data::Packet(packet.clone()));
+            }
+        }
+        data_set
+    }
+
+    /// The selected fields of a data set that are written, given which of them vary if known.
+    fn written_fields<'d>(&'d self, data_set: &'d DataSet, varying_columns: Option<&[bool]>) -> Vec<Field<'d>> {
+        let fields = self.selected_fields(data_set);
+        match varying_columns {
+            Some(varying_columns) => fields.into_iter().zip(varying_columns).filter(|(_, varying)| **varying).map(|(field, _)| field).collect(),
+            None => fields,
+        }
+    }
+
+    /// Write the data sets from `start_utc` up to, but excluding `end_utc` into an output file,
+    /// using the fields from the `layout` as columns.
+    ///
+    /// If an `existing` CSV file is given, the rows are appended to it instead, skipping the data
+    /// sets sharing the timestamp of its last row.
+    ///
+    /// Returns the uncommitted output, the layout of the data sets actually seen, whether any
+    /// data set was written and how many were older than one read before them.
+    fn write_day(&self, mut output: Box<dyn DayWriter + 'a>, recording: Recording<'_>, start_utc: DateTime<Utc>, end_utc: DateTime<Utc>, layout: &Layout, existing: Option<&ExistingCsv>) -> Result<(Box<dyn DayWriter + 'a>, Layout, bool, usize)> {
+        let topo_data_set = &self.with_missing_columns(&layout.topo_data_set);
+        let varying_columns = layout.varying_columns.as_deref();
+
+        // Timestamps are written with less than millisecond precision, so data sets following
+        // the last row may be written with the same timestamp
+        let last_timestamp = existing.and_then(|existing| existing.last_timestamp.as_deref());
+
+        output.write_header(&self.written_fields(topo_data_set, varying_columns))?;
+
+        let mut rr = recording.reader()?;
+        rr.set_min_max_timestamps(Some(start_utc), Some(end_utc));
+
+        let mut seen_topo_data_set = DataSet::new();
+
+        let mut resampler = self.resample.as_ref().map(|resample| Resampler::new(&self.spec, resample));
+
+        // The raw values of the written fields of the data set written last
+        let mut last_values = None;
+
+        let mut constants = Constants::default();
+
+        let mut sorted_data_sets = match self.out_of_order {
+            OutOfOrder::Sort => {
+                let mut data_sets = Vec::new();
+                while let Some(rr_data_set) = rr.read_data_set()? {
+                    data_sets.push(rr_data_set);
+                }
+                // Keeps data sets sharing a timestamp in the order they were recorded
+                data_sets.sort_by_key(|data_set| data_set.timestamp);
+                Some(data_sets.into_iter())
+            }
+            OutOfOrder::Warn | OutOfOrder::Drop => None,
+        };
+
+        // The latest timestamp read, to detect data sets older than that
+        let mut latest_timestamp = None;
+        let mut out_of_order = 0;
+
+        let mut contains_data_lines = false;
+        loop {
+            let rr_data_set = match &mut sorted_data_sets {
+                Some(data_sets) => data_sets.next(),
+                None => rr.read_data_set()?,
+            };
+            let Some(rr_data_set) = rr_data_set else {
+                break;
+            };
+
+            // Skip the data sets that only contained packets of other devices
+            let was_empty = rr_data_set.is_empty();
+            let rr_data_set = self.address_filter.apply(rr_data_set);
+            if rr_data_set.is_empty() && !was_empty {
+                continue;
+            }
+
+            if latest_timestamp.is_some_and(|latest_timestamp| rr_data_set.timestamp < latest_timestamp) {
+                out_of_order += 1;
+                if self.out_of_order == OutOfOrder::Drop {
+                    continue;
+                }
+            } else {
+                latest_timestamp = Some(rr_data_set.timestamp);
+            }
+
+            if last_timestamp.is_some_and(|last_timestamp| rr_data_set.timestamp.with_timezone(&self.tz).format(self.timestamp_format).to_string() == last_timestamp) {
+                continue;
+            }
+
+            for data in rr_data_set.iter() {
+                if !seen_topo_data_set.iter().any(|seen_data| seen_data == data) {
+                    seen_topo_data_set.add_data(data.clone());
+                }
+            }
+
+            let mut data_set = topo_data_set.clone();
+            data_set.timestamp = rr_data_set.timestamp;
+            data_set.add_data_set(rr_data_set);
+
+            let data_set = match &mut resampler {
+                Some(resampler) => match resampler.push(data_set) {
+                    Some(data_set) => data_set,
+                    None => continue,
+                },
+                None => data_set,
+            };
+
+            self.write_data_set(output.as_mut(), &data_set, varying_columns, &mut last_values, &mut constants)?;
+
+            contains_data_lines = true;
+        }
+
+        if let Some(data_set) = resampler.and_then(|mut resampler| resampler.finish()) {
+            self.write_data_set(output.as_mut(), &data_set, varying_columns, &mut last_values, &mut constants)?;
+
+            contains_data_lines = true;
+        }
+
+        seen_topo_data_set.clear_all_packets();
+        seen_topo_data_set.sort();
+
+        let seen_layout = Layout {
+            topo_data_set: seen_topo_data_set,
+            varying_columns: Some(constants.varying),
+        };
+
+        Ok((output, seen_layout, contains_data_lines, out_of_order))
+    }
+
+    /// Write a data set, unless only changes are written and its written fields have the same
+    /// values as those of the data set written last.
+    fn write_data_set(&self, output: &mut dyn DayWriter, data_set: &DataSet, varying_columns: Option<&[bool]>, last_values: &mut Option<Vec<Option<i64>>>, constants: &mut Constants) -> Result<()> {
+        if self.skip_constant {
+            constants.push(&self.selected_fields(data_set));
+        }
+
+        let fields = self.written_fields(data_set, varying_columns);
+        if self.changes_only {
+            let values = fields.iter().map(|field| *field.raw_value_i64()).collect::<Vec<_>>();
+            if last_values.as_ref() == Some(&values) {
+                return Ok(());
+            }
+            *last_values = Some(values);
+        }
+
+        output.write_data_set(data_set, &fields)
+    }
+}
+
+/// A CSV file written before, that rows are appended to.
+struct ExistingCsv {
+    contents: Vec<u8>,
+
+    /// The cells of the header row.
+    header: Vec<String>,
+
+    /// The timestamp of the last row, `None` if there are only headers.
+    last_timestamp: Option<String>,
+}
+
+/// Read an existing CSV file to append rows to, returning `None` if there is none.
+fn read_existing_csv(filename: &Path, delimiter: u8) -> Result<Option<ExistingCsv>> {
+    let contents = match std::fs::read(filename) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    // The last row might be incomplete if the file was not written by this tool
+    if !contents.ends_with(b"\n") {
+        return Ok(None);
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(&contents [..]);
+    let mut records = reader.records();
+
+    let header = match records.next() {
+        Some(record) => record?.iter().map(str::to_string).collect(),
+        None => return Ok(None),
+    };
+    let last_timestamp = match records.last() {
+        Some(record) => record?.get(0).map(str::to_string),
+        None => None,
+    };
+
+    Ok(Some(ExistingCsv {
+        contents,
+        header,
+        last_timestamp,
+    }))
+}
+
+/// The columns of an output file.
+struct Layout {
+    /// The packets whose fields are written.
+    topo_data_set: DataSet,
+
+    /// Which of the selected fields are written when skipping constant ones, all if `None`.
+    varying_columns: Option<Vec<bool>>,
+}
+
+/// Tracks which of the selected fields had differing values within a day.
+#[derive(Default)]
+struct Constants {
+    /// The raw values of the first data set.
+    first_values: Option<Vec<Option<i64>>>,
+
+    /// Whether each field had a value differing from the first one.
+    varying: Vec<bool>,
+}
+
+impl Constants {
+    fn push(&mut self, fields: &[Field<'_>]) {
+        let values = fields.iter().map(|field| *field.raw_value_i64());
+        match &self.first_values {
+            Some(first_values) => {
+                for ((varying, value), first_value) in self.varying.iter_mut().zip(values).zip(first_values) {
+                    *varying |= value != *first_value;
+                }
+            }
+            None => {
+                self.first_values = Some(values.collect());
+                self.varying = vec![false; fields.len()];
+            }
+        }
+    }
+}
+
+/// The `.vbus` data converted into an output file, which is read again for each pass over it.
+#[derive(Clone, Copy)]
+enum Recording<'r> {
+    /// `.vbus` files read as needed, whose corrupt records were already reported.
+    Files(&'r [PathBuf]),
+
+    /// The valid records of `.vbus` data in memory, e.g. read from a stream.
+    Bytes(&'r [u8]),
+}
+
+impl<'r> Recording<'r> {
+    /// A reader of the data sets, silently skipping corrupt records.
+    fn reader(self) -> Result<RecordingReader<Box<dyn Read + 'r>>> {
+        let reader: Box<dyn Read + 'r> = match self {
+            Recording::Files(filenames) => {
+                let mut reader: Box<dyn Read> = Box::new(std::io::empty());
+                for filename in filenames {
+                    reader = Box::new(reader.chain(ValidRecords::new(File::open(filename)?, |_, _| Ok(()))));
+                }
+                reader
+            }
+            Recording::Bytes(bytes) => Box::new(bytes),
+        };
+        Ok(RecordingReader::new(reader))
+    }
+}
+
+fn is_same_topology(left: &DataSet, right: &DataSet) -> bool {
+    (left.len() == right.len()) && left.iter().zip(right.iter()).all(|(l, r)| l == r)
+}
@@ -0,0 +1,35 @@
+use chrono::{Duration, NaiveDate};
+
+/// An inclusive range of days, unbounded on either side unless given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateRange {
+    /// The first day of the range.
+    pub from: Option<NaiveDate>,
+
+    /// The last day of the range.
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    /// Whether the given day lies within the range.
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+
+    /// Whether the day given as `YYYYMMDD` lies within the range. Datecodes that cannot be parsed
+    /// are only contained in an unbounded range.
+    pub fn contains_datecode(&self, datecode: &str) -> bool {
+        match NaiveDate::parse_from_str(datecode, "%Y%m%d") {
+            Ok(date) => self.contains(date),
+            Err(_) => self.from.is_none() && self.to.is_none(),
+        }
+    }
+
+    /// The range extended by the given number of days on either side.
+    pub(crate) fn widened(&self, days: i64) -> DateRange {
+        DateRange {
+            from: self.from.map(|from| from - Duration::days(days)),
+            to: self.to.map(|to| to + Duration::days(days)),
+        }
+    }
+}
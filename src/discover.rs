@@ -0,0 +1,103 @@
+use std::{collections::HashMap, net::{IpAddr, Ipv4Addr}, time::{Duration, Instant}};
+
+use async_std::net::UdpSocket;
+use http_types::Method;
+use tracing::debug;
+
+use crate::{client::{with_timeout, Client}, Host, Result, SyncOptions};
+
+/// The port RESOL devices listen on for VBus-over-TCP connections and discovery queries.
+const DISCOVERY_PORT: u16 = 7053;
+
+const DISCOVERY_QUERY: &[u8] = b"---RESOL-BROADCAST-QUERY---";
+
+const DISCOVERY_REPLY: &[u8] = b"---RESOL-BROADCAST-REPLY---";
+
+/// How long to wait for devices to answer the discovery query.
+const DISCOVERY_DURATION: Duration = Duration::from_secs(2);
+
+/// A device that answered the discovery query.
+#[derive(Debug, Clone)]
+pub struct DiscoveredDevice {
+    /// The address the device answered from.
+    pub address: IpAddr,
+
+    /// The product name reported by the device, e.g. `DL2`.
+    pub product: Option<String>,
+
+    /// The name given to the device by its owner.
+    pub name: Option<String>,
+}
+
+/// Find RESOL devices on the local network.
+///
+/// RESOL devices do not announce themselves using mDNS, but answer a UDP broadcast query on port
+/// 7053. The product and name of each device are then requested from its web interface, which
+/// is skipped for devices that do not answer that request.
+pub async fn discover(options: &SyncOptions) -> Result<Vec<DiscoveredDevice>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+
+    debug!("Sending discovery query");
+
+    socket.send_to(DISCOVERY_QUERY, (Ipv4Addr::BROADCAST, DISCOVERY_PORT)).await?;
+
+    let deadline = Instant::now() + DISCOVERY_DURATION;
+    let mut addresses = Vec::new();
+    let mut buf = [0u8; 64];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let (len, addr) = match async_std::future::timeout(remaining, socket.recv_from(&mut buf)).await {
+            Ok(result) => result?,
+            Err(_) => break,
+        };
+        // Our own query may be received as well, and devices may answer more than once
+        if buf [..len].starts_with(DISCOVERY_REPLY) && !addresses.contains(&addr.ip()) {
+            debug!("Discovered device at {}", addr.ip());
+            addresses.push(addr.ip());
+        }
+    }
+
+    addresses.sort();
+
+    let mut devices = Vec::new();
+    for address in addresses {
+        let info = match fetch_device_information(address, options).await {
+            Ok(info) => info,
+            Err(err) => {
+                debug!("Unable to fetch device information from {}: {}", address, err);
+                HashMap::new()
+            }
+        };
+        devices.push(DiscoveredDevice {
+            address,
+            product: info.get("product").cloned(),
+            name: info.get("name").cloned(),
+        });
+    }
+
+    Ok(devices)
+}
+
+/// Request the `key = "value"` lines describing a device.
+async fn fetch_device_information(address: IpAddr, options: &SyncOptions) -> Result<HashMap<String, String>> {
+    let host = Host::parse(&address.to_string(), options)?;
+    let client = Client::new(&host, options);
+
+    let (conn, mut res) = client.send(Method::Get, "/cgi-bin/get_resol_device_information").await?;
+
+    if !res.status().is_success() {
+        return Err(format!("Unable to fetch device information, got status {}", res.status()).into());
+    }
+
+    let what = "fetching device information".to_string();
+    let body = with_timeout(options.timeout, what, async { Ok(res.body_string().await?) }).await?;
+
+    client.release(conn, &res);
+
+    let info = body.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().trim_matches('"').to_string()))
+        .collect();
+
+    Ok(info)
+}
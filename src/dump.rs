@@ -0,0 +1,36 @@
+use std::{fs::File, io::Write, path::PathBuf};
+
+use resol_vbus::{live_data_encoder::{bytes_from_data, length_from_data}, Data, RecordingReader};
+use tracing::warn;
+
+use crate::{valid_records::ValidRecords, Result};
+
+/// Write each packet, datagram and telegram recorded in the `.vbus` files as a line holding its
+/// timestamp, channel and raw VBus frame bytes in hex, without decoding any fields.
+///
+/// Corrupt records are skipped with a warning, like `convert` does. Returns the number of lines
+/// written.
+pub fn dump_raw(filenames: &[PathBuf], output: &mut dyn Write) -> Result<usize> {
+    let mut lines = 0;
+    for filename in filenames {
+        let records = ValidRecords::new(File::open(filename)?, |offset, reason| {
+            warn!("Skipping corrupt record in {:?} at offset {}: {}", filename, offset, reason);
+            Ok(())
+        });
+        let mut rr = RecordingReader::new(records);
+        while let Some(data_set) = rr.read_data_set()? {
+            for data in data_set.iter() {
+                writeln!(output, "{}\t{}\t{}", data.as_header().timestamp.to_rfc3339(), data.as_header().channel, hex_bytes(data))?;
+                lines += 1;
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// The bytes of the data as sent on the VBus, as uppercase hex without separators.
+fn hex_bytes(data: &Data) -> String {
+    let mut bytes = vec![0; length_from_data(data)];
+    bytes_from_data(data, &mut bytes);
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+/// The errors that can occur while syncing or converting log files.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a local file or socket failed.
+    Io(std::io::Error),
+
+    /// Establishing a TLS connection failed.
+    Tls(async_native_tls::Error),
+
+    /// Sending an HTTP request or receiving its response failed.
+    Http(http_types::Error),
+
+    /// Reading the VBus specification or a VBus recording failed.
+    Vbus(resol_vbus::Error),
+
+    /// Writing a CSV file failed.
+    Csv(csv::Error),
+
+    /// Reading from or writing to a SQLite database failed.
+    Sqlite(rusqlite::Error),
+
+    /// Writing a Parquet file failed.
+    Parquet(parquet::errors::ParquetError),
+
+    /// A host, URL, datecode or config file could not be parsed.
+    Parse(Box<dyn std::error::Error + Send + Sync>),
+
+    /// The host rejected the given credentials.
+    Auth(String),
+
+    /// An operation did not complete within the configured timeout.
+    Timeout(String, Duration),
+
+    /// The operation was interrupted before it completed, e.g. by Ctrl-C.
+    Interrupted,
+
+    /// Any other failure, described by its message.
+    Other(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Tls(err) => write!(f, "TLS error: {}", err),
+            Error::Http(err) => write!(f, "HTTP error: {}", err),
+            Error::Vbus(err) => write!(f, "VBus error: {}", err),
+            Error::Csv(err) => write!(f, "CSV error: {}", err),
+            Error::Sqlite(err) => write!(f, "SQLite error: {}", err),
+            Error::Parquet(err) => write!(f, "Parquet error: {}", err),
+            Error::Parse(err) => write!(f, "Parse error: {}", err),
+            Error::Auth(host) => write!(f, "Authentication failed for host {:?}", host),
+            Error::Timeout(what, after) => write!(f, "Timed out after {:?} {}", after, what),
+            Error::Interrupted => f.write_str("Interrupted"),
+            Error::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Tls(err) => Some(err),
+            Error::Http(err) => Some(err.as_ref()),
+            Error::Vbus(err) => Some(err),
+            Error::Csv(err) => Some(err),
+            Error::Sqlite(err) => Some(err),
+            Error::Parquet(err) => Some(err),
+            Error::Parse(err) => Some(err.as_ref()),
+            Error::Auth(_) | Error::Timeout(..) | Error::Interrupted | Error::Other(_) => None,
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(other: String) -> Error {
+        Error::Other(other)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(other: &str) -> Error {
+        Error::Other(other.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(other: std::io::Error) -> Error {
+        Error::Io(other)
+    }
+}
+
+impl From<async_native_tls::Error> for Error {
+    fn from(other: async_native_tls::Error) -> Error {
+        Error::Tls(other)
+    }
+}
+
+impl From<http_types::Error> for Error {
+    fn from(other: http_types::Error) -> Error {
+        Error::Http(other)
+    }
+}
+
+impl From<resol_vbus::Error> for Error {
+    fn from(other: resol_vbus::Error) -> Error {
+        Error::Vbus(other)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(other: csv::Error) -> Error {
+        Error::Csv(other)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(other: rusqlite::Error) -> Error {
+        Error::Sqlite(other)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(other: parquet::errors::ParquetError) -> Error {
+        Error::Parquet(other)
+    }
+}
+
+impl From<std::num::ParseIntError> for Error {
+    fn from(other: std::num::ParseIntError) -> Error {
+        Error::Parse(Box::new(other))
+    }
+}
+
+impl From<chrono::ParseError> for Error {
+    fn from(other: chrono::ParseError) -> Error {
+        Error::Parse(Box::new(other))
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(other: toml::de::Error) -> Error {
+        Error::Parse(Box::new(other))
+    }
+}
+
+impl From<http_types::url::ParseError> for Error {
+    fn from(other: http_types::url::ParseError) -> Error {
+        Error::Parse(Box::new(other))
+    }
+}
+
+/// A result type using this crate's `Error`.
+pub type Result<T> = std::result::Result<T, Error>;
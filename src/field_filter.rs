@@ -0,0 +1,55 @@
+/// Selects the fields to convert by their names.
+///
+/// Patterns are globs matched case-insensitively against the whole field name, where `*` matches
+/// any number of characters and `?` a single one. A field is selected if it matches any of the
+/// `include` patterns (or there are none) and none of the `exclude` patterns.
+#[derive(Debug, Clone, Default)]
+pub struct FieldFilter {
+    /// Only convert the fields matching one of these patterns, all if empty.
+    pub include: Vec<String>,
+
+    /// Do not convert the fields matching one of these patterns.
+    pub exclude: Vec<String>,
+}
+
+impl FieldFilter {
+    /// Whether the field with the given name is selected.
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase().chars().collect::<Vec<_>>();
+        let matches_any = |patterns: &[String]| patterns.iter().any(|pattern| {
+            let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+            glob_matches(&pattern, &name)
+        });
+
+        (self.include.is_empty() || matches_any(&self.include)) && !matches_any(&self.exclude)
+    }
+}
+
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the position in the name it was tried to match up to
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(c) if *c == '?' || *c == name [n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern [p..].iter().all(|c| *c == '*')
+}
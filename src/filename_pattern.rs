@@ -0,0 +1,129 @@
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+/// The placeholders a `FilenamePattern` may contain.
+const PLACEHOLDERS: &[&str] = &["date", "host", "yyyy", "mm", "dd"];
+
+/// The name of the output file of a day relative to the host directory, without extension,
+/// like `{yyyy}/{mm}/{dd}` or `{host}_{date}`.
+///
+/// Placeholders are `{date}` (`YYYYMMDD`), `{yyyy}`, `{mm}`, `{dd}` and `{host}` (the name of the
+/// host directory). A `/` creates a subdirectory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenamePattern {
+    pattern: String,
+}
+
+impl FilenamePattern {
+    /// The name of the output file for the given local day.
+    pub fn expand(&self, date: NaiveDate, host: &str) -> String {
+        self.pattern
+            .replace("{date}", &date.format("%Y%m%d").to_string())
+            .replace("{yyyy}", &date.format("%Y").to_string())
+            .replace("{mm}", &date.format("%m").to_string())
+            .replace("{dd}", &date.format("%d").to_string())
+            .replace("{host}", host)
+    }
+
+    /// The local day an output file was named after by `expand`, if the name matches the pattern.
+    pub(crate) fn parse_date(&self, name: &str, host: &str) -> Option<NaiveDate> {
+        let (mut year, mut month, mut day) = (None, None, None);
+        let mut pattern = self.pattern.as_str();
+        let mut name = name;
+        while let Some(c) = pattern.chars().next() {
+            let placeholder = pattern.strip_prefix('{').and_then(|rest| rest.split_once('}'));
+            if let Some((placeholder, rest)) = placeholder {
+                pattern = rest;
+                name = match placeholder {
+                    "date" => {
+                        let (date, rest) = digits(name, 8)?;
+                        set_once(&mut year, date / 10000)?;
+                        set_once(&mut month, date / 100 % 100)?;
+                        set_once(&mut day, date % 100)?;
+                        rest
+                    }
+                    "yyyy" | "mm" | "dd" => {
+                        let (part, count) = match placeholder {
+                            "yyyy" => (&mut year, 4),
+                            "mm" => (&mut month, 2),
+                            _ => (&mut day, 2),
+                        };
+                        let (value, rest) = digits(name, count)?;
+                        set_once(part, value)?;
+                        rest
+                    }
+                    "host" => name.strip_prefix(host)?,
+                    _ => return None,
+                };
+            } else {
+                pattern = &pattern [c.len_utf8()..];
+                name = name.strip_prefix(c)?;
+            }
+        }
+
+        if !name.is_empty() {
+            return None;
+        }
+        NaiveDate::from_ymd_opt(year? as i32, month?, day?)
+    }
+}
+
+/// The number made of the first `count` characters, if they are digits, and the rest.
+fn digits(s: &str, count: usize) -> Option<(u32, &str)> {
+    let prefix = s.get(..count).filter(|prefix| prefix.chars().all(|c| c.is_ascii_digit()))?;
+    Some((prefix.parse().ok()?, &s [count..]))
+}
+
+/// Store a part of a date, unless another placeholder already gave a different one.
+fn set_once(part: &mut Option<u32>, value: u32) -> Option<()> {
+    match *part {
+        Some(existing) if existing != value => None,
+        _ => {
+            *part = Some(value);
+            Some(())
+        }
+    }
+}
+
+impl Default for FilenamePattern {
+    fn default() -> FilenamePattern {
+        FilenamePattern {
+            pattern: "{date}".to_string(),
+        }
+    }
+}
+
+impl FromStr for FilenamePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<FilenamePattern, String> {
+        let mut placeholders = Vec::new();
+        let mut rest = s;
+        while let Some(index) = rest.find('{') {
+            let (placeholder, after) = match rest [index + 1..].split_once('}') {
+                Some(split) => split,
+                None => return Err(format!("Unclosed placeholder in filename pattern {:?}", s)),
+            };
+            if !PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!("Unknown placeholder {{{}}} in filename pattern {:?}, expected one of {{date}}, {{host}}, {{yyyy}}, {{mm}} or {{dd}}", placeholder, s));
+            }
+            placeholders.push(placeholder);
+            rest = after;
+        }
+
+        // Otherwise several days would be written into the same file
+        let has_date = placeholders.contains(&"date") || ["yyyy", "mm", "dd"].iter().all(|placeholder| placeholders.contains(placeholder));
+        if !has_date {
+            return Err(format!("Filename pattern {:?} must contain {{date}} or {{yyyy}}, {{mm}} and {{dd}}", s));
+        }
+
+        if s.starts_with('/') || s.split(['/', '\\']).any(|component| component == "..") {
+            return Err(format!("Filename pattern {:?} must stay within the host directory", s));
+        }
+
+        Ok(FilenamePattern {
+            pattern: s.to_string(),
+        })
+    }
+}
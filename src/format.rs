@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+use chrono::format::{Item, StrftimeItems};
+use resol_vbus::{specification_file::Type, Language};
+
+use crate::output::Field;
+
+/// The character separating the integer part of numbers from their fractional part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecimalSeparator {
+    /// A decimal point, like `22.5`.
+    Point,
+
+    /// A decimal comma, like `22,5`.
+    Comma,
+}
+
+impl DecimalSeparator {
+    /// The separator the specification uses when formatting numbers in the given language.
+    pub fn for_language(language: Language) -> DecimalSeparator {
+        match language {
+            Language::En => DecimalSeparator::Point,
+            Language::De | Language::Fr => DecimalSeparator::Comma,
+        }
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            DecimalSeparator::Point => '.',
+            DecimalSeparator::Comma => ',',
+        }
+    }
+}
+
+impl FromStr for DecimalSeparator {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<DecimalSeparator, String> {
+        match s {
+            "point" | "." => Ok(DecimalSeparator::Point),
+            "comma" | "," => Ok(DecimalSeparator::Comma),
+            _ => Err(format!("Unknown decimal separator {:?}, expected point or comma", s)),
+        }
+    }
+}
+
+/// How the values of fields are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueStyle {
+    /// Just the value, like `22,6`, for further processing.
+    #[default]
+    Raw,
+
+    /// The value followed by its unit, like `22,6 °C`, for reading by humans.
+    ///
+    /// The VBus specification does not name the states of enumerations, so those are still
+    /// written as numbers.
+    Formatted,
+}
+
+impl FromStr for ValueStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<ValueStyle, String> {
+        match s {
+            "raw" => Ok(ValueStyle::Raw),
+            "formatted" => Ok(ValueStyle::Formatted),
+            _ => Err(format!("Unknown value style {:?}, expected raw or formatted", s)),
+        }
+    }
+}
+
+/// The format of the timestamps in the first column of the CSV files, unless given otherwise.
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%d.%m.%Y %H:%M:%S";
+
+/// The format of RFC 3339 timestamps including the offset of the time zone, like
+/// `2024-04-01T00:00:00+02:00`.
+pub const RFC3339_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%:z";
+
+/// Parse a chrono format string for the timestamps of the CSV files, like `%Y-%m-%dT%H:%M:%S%z`,
+/// rejecting unknown specifiers that would fail when writing the rows.
+pub fn parse_timestamp_format(format: &str) -> std::result::Result<String, String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        Err(format!("Invalid timestamp format {:?}", format))
+    } else {
+        Ok(format.to_string())
+    }
+}
+
+/// Formats the cells of the CSV files.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CsvFormat {
+    /// The separator used by the specification.
+    spec_separator: DecimalSeparator,
+
+    /// The separator to write instead.
+    separator: DecimalSeparator,
+
+    /// The character between two cells.
+    delimiter: u8,
+
+    /// Whether to append units to the values.
+    value_style: ValueStyle,
+
+    /// Whether to start the files with a UTF-8 byte order mark.
+    bom: bool,
+}
+
+impl CsvFormat {
+    pub(crate) fn new(language: Language, separator: Option<DecimalSeparator>, delimiter: u8, value_style: ValueStyle, bom: bool) -> CsvFormat {
+        let spec_separator = DecimalSeparator::for_language(language);
+        CsvFormat {
+            spec_separator,
+            separator: separator.unwrap_or(spec_separator),
+            delimiter,
+            value_style,
+            bom,
+        }
+    }
+
+    pub(crate) fn delimiter(&self) -> u8 {
+        self.delimiter
+    }
+
+    pub(crate) fn bom(&self) -> bool {
+        self.bom
+    }
+
+    /// Format the value of a field.
+    pub(crate) fn format_field(&self, field: &Field<'_>) -> String {
+        let append_unit = self.value_style == ValueStyle::Formatted;
+        self.format_value(field.field_spec().typ, field.fmt_raw_value(append_unit).to_string())
+    }
+
+    /// Format a number with the given number of fractional digits.
+    pub(crate) fn format_number(&self, value: f64, precision: i32) -> String {
+        let value = format!("{:.*}", precision.max(0) as usize, value);
+        value.replace('.', &self.separator.as_char().to_string())
+    }
+
+    /// Adjust a value as formatted by the specification, swapping the decimal separator of
+    /// numbers if needed.
+    fn format_value(&self, typ: Type, value: String) -> String {
+        if typ == Type::Number && self.separator != self.spec_separator {
+            value.replace(self.spec_separator.as_char(), &self.separator.as_char().to_string())
+        } else {
+            value
+        }
+    }
+}
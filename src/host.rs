@@ -0,0 +1,257 @@
+use std::net::{IpAddr, Ipv6Addr};
+
+use http_types::{auth::BasicAuth, Method, Request, Response, StatusCode, Url};
+
+use crate::{Error, Result, SyncOptions};
+
+/// The directory listing the log files on most devices.
+const DEFAULT_LOG_PATH: &str = "/log/";
+
+/// Normalize the path of a directory listing log files to end with a slash, rejecting anything
+/// that is not a plain path.
+fn parse_log_path(path: &str) -> Option<String> {
+    if path.contains(['?', '#', '\\']) || path.split('/').any(|component| component == "..") {
+        return None;
+    }
+    if path.trim_matches('/').is_empty() {
+        return Some(DEFAULT_LOG_PATH.to_string());
+    }
+    let path = path.trim_end_matches('/');
+    Some(format!("{}/", path))
+}
+
+/// Whether a host name consists of labels of letters, digits, hyphens and underscores, or is an
+/// IP address.
+fn is_valid_name(name: &str) -> bool {
+    if name.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    let name = name.strip_suffix('.').unwrap_or(name);
+    !name.is_empty() && name.len() <= 253 && name.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63 && !label.starts_with('-') && !label.ends_with('-') && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
+/// A host name, with IPv6 literals enclosed in brackets to allow appending a port.
+pub(crate) fn bracketed(name: &str) -> String {
+    if name.parse::<Ipv6Addr>().is_ok() {
+        format!("[{}]", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// A data logging device to download log files from.
+#[derive(Debug)]
+pub struct Host {
+    /// The host name or IP address, without brackets for IPv6 literals.
+    pub name: String,
+
+    /// The port the web interface is reachable on.
+    pub port: u16,
+
+    /// Whether to connect using TLS.
+    pub tls: bool,
+
+    /// The path of the directory listing the log files, `/log/` unless the device has several.
+    pub log_path: String,
+
+    auth: Option<BasicAuth>,
+}
+
+impl Host {
+    /// Parse a host given as `[http[s]://][user[:pass]@]name[:port][/path]`, falling back to the
+    /// `options` for TLS and credentials.
+    ///
+    /// The path selects the directory listing the log files, e.g. `/log/controller1` on devices
+    /// with several loggers, instead of `/log/`.
+    pub fn parse(arg: &str, options: &SyncOptions) -> Result<Host> {
+        let (tls, rest) = if let Some(rest) = arg.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = arg.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            (options.tls, arg)
+        };
+
+        let (credentials, rest) = match rest.rsplit_once('@') {
+            Some((credentials, rest)) => (Some(credentials), rest),
+            None => (None, rest),
+        };
+
+        let (rest, log_path) = match rest.find('/') {
+            Some(index) => match parse_log_path(&rest[index..]) {
+                Some(log_path) => (&rest[..index], log_path),
+                None => return Err(Error::Parse(format!("Invalid path in host {:?}", arg).into())),
+            },
+            None => (rest, DEFAULT_LOG_PATH.to_string()),
+        };
+
+        let default_port = if tls { 443 } else { 80 };
+
+        let parse_port = |port: &str| {
+            port.parse::<u16>().map_err(|_| Error::Parse(format!("Invalid port in host {:?}", arg).into()))
+        };
+
+        let (name, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+            match bracketed.split_once(']') {
+                Some((name, _)) if name.parse::<Ipv6Addr>().is_err() => return Err(Error::Parse(format!("Invalid IPv6 address {:?} in host {:?}", name, arg).into())),
+                Some((name, "")) => (name, default_port),
+                Some((name, port)) => match port.strip_prefix(':') {
+                    Some(port) => (name, parse_port(port)?),
+                    None => return Err(Error::Parse(format!("Invalid host {:?}", arg).into())),
+                },
+                None => return Err(Error::Parse(format!("Invalid host {:?}", arg).into())),
+            }
+        } else if rest.parse::<Ipv6Addr>().is_ok() {
+            (rest, default_port)
+        } else {
+            match rest.split_once(':') {
+                Some((name, port)) => (name, parse_port(port)?),
+                None => (rest, default_port),
+            }
+        };
+
+        if !is_valid_name(name) {
+            return Err(Error::Parse(format!("Invalid host name {:?} in {:?}, expected a host name or IP address", name, arg).into()));
+        }
+        if port == 0 {
+            return Err(Error::Parse(format!("Invalid port in host {:?}", arg).into()));
+        }
+
+        let auth = if let Some(credentials) = credentials {
+            let (username, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+            Some(BasicAuth::new(username, password))
+        } else if let Some(username) = &options.username {
+            let password = options.password.as_deref().unwrap_or("");
+            Some(BasicAuth::new(username, password))
+        } else {
+            None
+        };
+
+        Ok(Host {
+            name: name.to_string(),
+            port,
+            tls,
+            log_path,
+            auth,
+        })
+    }
+
+    fn default_port(&self) -> u16 {
+        if self.tls {
+            443
+        } else {
+            80
+        }
+    }
+
+    /// The directory the files of this host are stored in.
+    ///
+    /// Other log paths than `/log/` are appended, like `<HOST>_controller1` for
+    /// `/log/controller1/`, so that the log files of several loggers are not mixed.
+    pub fn dir_name(&self) -> String {
+        let mut dir_name = if self.port == self.default_port() {
+            self.name.clone()
+        } else {
+            format!("{}_{}", self.name, self.port)
+        };
+        if self.log_path != DEFAULT_LOG_PATH {
+            let log_path = self.log_path.trim_matches('/');
+            let log_path = log_path.strip_prefix("log/").unwrap_or(log_path);
+            dir_name.push('_');
+            dir_name.push_str(&log_path.replace('/', "_"));
+        }
+        dir_name
+    }
+
+    /// The path of a log file within the log path.
+    pub(crate) fn log_file_path(&self, datecode: &str) -> String {
+        format!("{}{}_packets.vbus", self.log_path, datecode)
+    }
+
+    /// The value of the `Host` header sent to the host, omitting the default port.
+    pub(crate) fn authority(&self) -> String {
+        if self.port == self.default_port() {
+            self.bracketed_name()
+        } else {
+            self.addr()
+        }
+    }
+
+    /// The host name, with IPv6 literals enclosed in brackets to allow appending a port.
+    fn bracketed_name(&self) -> String {
+        bracketed(&self.name)
+    }
+
+    pub(crate) fn addr(&self) -> String {
+        self.addr_with_port(self.port)
+    }
+
+    /// The address of another service of the host, like the VBus-over-TCP port.
+    pub(crate) fn addr_with_port(&self, port: u16) -> String {
+        format!("{}:{}", self.bracketed_name(), port)
+    }
+
+    /// The scheme, name and port identifying the connections to the host.
+    pub(crate) fn origin(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}", scheme, self.addr())
+    }
+
+    /// The host a request was redirected to, keeping the credentials if the name stays the same
+    /// and the connection does not change from HTTPS to HTTP.
+    pub fn redirected(&self, url: &Url) -> Result<Host> {
+        let tls = match url.scheme() {
+            "https" => true,
+            "http" => false,
+            _ => return Err(format!("Unsupported redirect to {}", url).into()),
+        };
+
+        let name = match url.host_str() {
+            Some(name) => name.trim_start_matches('[').trim_end_matches(']').to_string(),
+            None => return Err(format!("Unsupported redirect to {}", url).into()),
+        };
+
+        // Credentials sent using TLS so far are never sent unencrypted
+        let auth = match &self.auth {
+            Some(auth) if name == self.name && (tls || !self.tls) => Some(BasicAuth::new(auth.username(), auth.password())),
+            _ => None,
+        };
+
+        Ok(Host {
+            port: url.port_or_known_default().unwrap_or(if tls { 443 } else { 80 }),
+            name,
+            tls,
+            log_path: self.log_path.clone(),
+            auth,
+        })
+    }
+
+    /// Whether requests to the host are sent with Basic Auth credentials.
+    pub fn has_credentials(&self) -> bool {
+        self.auth.is_some()
+    }
+
+    pub(crate) fn url(&self, path: &str) -> Result<Url> {
+        let scheme = if self.tls { "https" } else { "http" };
+        let url = format!("{}://{}:{}{}", scheme, self.bracketed_name(), self.port, path);
+        Ok(Url::parse(&url)?)
+    }
+
+    pub(crate) fn request(&self, method: Method, path: &str) -> Result<Request> {
+        let mut req = Request::new(method, self.url(path)?);
+        if let Some(auth) = &self.auth {
+            auth.apply(&mut req);
+        }
+        Ok(req)
+    }
+
+    pub(crate) fn check_authorized(&self, res: &Response) -> Result<()> {
+        if res.status() == StatusCode::Unauthorized {
+            Err(Error::Auth(self.name.clone()))
+        } else {
+            Ok(())
+        }
+    }
+}
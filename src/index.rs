@@ -0,0 +1,141 @@
+use scraper::{ElementRef, Html, Node, Selector};
+
+/// The suffix of the log files the index links to, following their `YYYYMMDD` datecode.
+const LOG_FILE_SUFFIX: &str = "_packets.vbus";
+
+/// A log file listed in the HTML index of `/log/`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogFileEntry {
+    /// The day of the log file as `YYYYMMDD`.
+    pub datecode: String,
+
+    /// The size of the log file in bytes, if listed next to the link.
+    pub size: Option<u64>,
+}
+
+/// Extract the log files linked from the HTML index of `/log/`.
+///
+/// Links may be relative (`20240101_packets.vbus`) or absolute (`/log/20240101_packets.vbus`),
+/// depending on the firmware. Links to other files are ignored, as are duplicates. The size is
+/// taken from the rest of the link's line, as a number followed by a unit (like `(13392 bytes)`)
+/// or as the last word, so that dates listed in between are skipped, or else from a following
+/// element holding just a number (like the next table cell).
+pub fn parse_log_index(html: &str) -> Vec<LogFileEntry> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("a[href]").expect("Selector should be valid");
+
+    let mut entries: Vec<LogFileEntry> = Vec::new();
+    for anchor in document.select(&selector) {
+        let Some(href) = anchor.value().attr("href") else {
+            continue;
+        };
+
+        let path = href.split(['?', '#']).next().unwrap_or_default();
+        let filename = path.rsplit('/').next().unwrap_or_default();
+
+        if let Some(datecode) = filename.strip_suffix(LOG_FILE_SUFFIX) {
+            if datecode.len() == 8 && datecode.chars().all(|c| c.is_ascii_digit()) && !entries.iter().any(|entry| entry.datecode == datecode) {
+                entries.push(LogFileEntry {
+                    datecode: datecode.to_string(),
+                    size: size_after(anchor),
+                });
+            }
+        }
+    }
+    entries
+}
+
+/// The texts of links to the next page of an index, besides arrows like `»`.
+const NEXT_PAGE_TEXTS: &[&str] = &["next", "next page", "more", "older", "weiter", "nächste", "nächste seite", "ältere", "suivant", "page suivante"];
+
+/// Find the link to the next page of an index the firmware splits into several pages, if any.
+///
+/// That is a link marked `rel="next"`, or else one whose text reads `Next`, `Weiter`, `Suivant`
+/// or similar, or consists of arrows like `»` or `>>`. Returns the link as given, which may be
+/// relative to the page.
+pub fn parse_next_page_link(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+
+    let rel_selector = Selector::parse("a[href][rel~=next], link[href][rel~=next]").expect("Selector should be valid");
+    if let Some(element) = document.select(&rel_selector).next() {
+        return element.value().attr("href").map(str::to_string);
+    }
+
+    let selector = Selector::parse("a[href]").expect("Selector should be valid");
+    document.select(&selector).find(|anchor| {
+        let text = anchor.text().collect::<String>().to_lowercase();
+        let text = text.trim_matches(|c: char| c.is_whitespace() || matches!(c, '»' | '›' | '>' | '→'));
+        let is_arrow = text.is_empty() && !anchor.text().collect::<String>().trim().is_empty();
+        is_arrow || NEXT_PAGE_TEXTS.contains(&text)
+    }).and_then(|anchor| anchor.value().attr("href")).map(str::to_string)
+}
+
+/// Find the size following a link, stopping at the next link.
+fn size_after(anchor: ElementRef<'_>) -> Option<u64> {
+    let mut element = anchor;
+    loop {
+        for sibling in element.next_siblings() {
+            match sibling.value() {
+                Node::Text(text) => {
+                    // The line ends the entry in preformatted listings
+                    let text = text.trim_start();
+                    let (line, is_end_of_line) = match text.split_once('\n') {
+                        Some((line, _)) => (line, true),
+                        None => (text, false),
+                    };
+                    let size = size_in_line(&words(line));
+                    if size.is_some() || is_end_of_line {
+                        return size;
+                    }
+                }
+                Node::Element(_) => {
+                    let sibling = ElementRef::wrap(sibling)?;
+                    if sibling.value().name() == "a" || sibling.descendants().any(|node| matches!(node.value(), Node::Element(child) if child.name() == "a")) {
+                        return None;
+                    }
+                    let text = sibling.text().collect::<String>();
+                    if let size @ Some(_) = size_in_cell(&words(&text)) {
+                        return size;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Continue with the siblings of the parent, e.g. the table cells following the link's one
+        element = ElementRef::wrap(element.parent()?)?;
+        if matches!(element.value().name(), "body" | "html" | "ul" | "ol" | "table" | "tbody" | "pre") {
+            return None;
+        }
+    }
+}
+
+/// The units a size may be followed by.
+const SIZE_UNITS: &[&str] = &["b", "byte", "bytes"];
+
+/// The words of a text, without the parentheses and commas around them.
+fn words(text: &str) -> Vec<&str> {
+    text.split_whitespace().map(|word| word.trim_matches(['(', ')', ','])).filter(|word| !word.is_empty()).collect()
+}
+
+fn is_number(word: &str) -> bool {
+    word.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_unit(word: &str) -> bool {
+    SIZE_UNITS.contains(&word.to_lowercase().as_str())
+}
+
+/// The size within a line, either a number followed by a unit or a number ending the line.
+fn size_in_line(words: &[&str]) -> Option<u64> {
+    let with_unit = words.windows(2).find(|pair| is_number(pair [0]) && is_unit(pair [1])).map(|pair| pair [0]);
+    with_unit.or(words.last().copied().filter(|word| is_number(word)))?.parse().ok()
+}
+
+/// The size given by an element like a table cell, which must hold nothing else but a unit.
+fn size_in_cell(words: &[&str]) -> Option<u64> {
+    match words {
+        [size] | [size, _] if is_number(size) && words [1..].iter().all(|word| is_unit(word)) => size.parse().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,51 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{atomic::write_file_atomically, Result};
+
+/// The log directory index of a host as received during the last sync, stored in a
+/// `log-index.json` file in its directory.
+///
+/// The log files that were up to date back then are not checked again while they are listed with
+/// the same size, except for the newest one, which the device may still be appending to.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct IndexCache {
+    /// The `ETag` header the index was served with, to ask the device whether it changed.
+    pub(crate) etag: Option<String>,
+
+    /// The body of the index.
+    pub(crate) body: String,
+
+    /// The bodies of the following pages, if the device splits the index into several.
+    #[serde(default)]
+    pub(crate) pages: Vec<String>,
+
+    /// The sizes of the log files by datecode that were up to date after the last sync.
+    pub(crate) complete: BTreeMap<String, u64>,
+}
+
+impl IndexCache {
+    fn filename(dir: &Path) -> PathBuf {
+        dir.join("log-index.json")
+    }
+
+    /// Read the index stored in a host directory, if any.
+    pub(crate) fn load(dir: &Path) -> Option<IndexCache> {
+        let contents = std::fs::read(IndexCache::filename(dir)).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    pub(crate) fn store(&self, dir: &Path) -> Result<()> {
+        let contents = serde_json::to_vec(self).map_err(std::io::Error::from)?;
+        write_file_atomically(&IndexCache::filename(dir), &contents)
+    }
+
+    /// Whether the log file was up to date after the last sync and still has the same size.
+    pub(crate) fn is_complete(&self, datecode: &str, vbus_filename: &Path) -> bool {
+        match (self.complete.get(datecode), std::fs::metadata(vbus_filename)) {
+            (Some(size), Ok(metadata)) => metadata.len() == *size,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use http_types::Method;
+use tracing::debug;
+
+use crate::{client::{read_body_with_progress, Client}, Error, Host, Result, SyncOptions};
+
+/// Number of lines sent per request, as recommended by InfluxDB.
+const LINES_PER_REQUEST: usize = 5000;
+
+/// The `/write` endpoint of an InfluxDB server.
+#[derive(Debug)]
+pub struct InfluxTarget {
+    /// The server to connect to.
+    pub host: Host,
+
+    /// The path and query of the endpoint, e.g. `/write?db=vbus`.
+    pub path: String,
+}
+
+impl InfluxTarget {
+    /// Parse a URL given as `[http[s]://][user[:pass]@]host[:port][/path]`, with the path
+    /// defaulting to `/write`.
+    ///
+    /// Credentials given for the devices are not sent to the InfluxDB server.
+    pub fn parse(url: &str) -> Result<InfluxTarget> {
+        let scheme_len = url.find("://").map_or(0, |index| index + 3);
+        let (host, path) = match url [scheme_len..].find(['/', '?']) {
+            Some(index) => url.split_at(scheme_len + index),
+            None => (url, "/write"),
+        };
+
+        let path = if path.starts_with('?') {
+            format!("/write{}", path)
+        } else {
+            path.to_string()
+        };
+
+        Ok(InfluxTarget {
+            host: Host::parse(host, &SyncOptions::default())?,
+            path,
+        })
+    }
+}
+
+/// POST the given line protocol files to an InfluxDB server.
+///
+/// Points are identified by their tags and timestamp, so uploading a file again replaces the
+/// points written before.
+pub async fn upload_to_influx(target: &InfluxTarget, filenames: &[PathBuf], options: &SyncOptions) -> Result<()> {
+    let client = Client::new(&target.host, options);
+
+    for filename in filenames {
+        debug!("Uploading {:?} to {:?}...", filename, target.host.name);
+
+        let contents = async_std::fs::read_to_string(filename).await?;
+        let lines = contents.lines().collect::<Vec<_>>();
+
+        for batch in lines.chunks(LINES_PER_REQUEST) {
+            let body = batch.join("\n");
+            let headers = [("Content-Type", "text/plain; charset=utf-8".to_string())];
+
+            // Writing the same points again overwrites them, so failed batches may be retried
+            let (conn, mut res) = client.send_with_body(Method::Post, &target.path, &headers, Some(body.as_bytes()), true).await?;
+            target.host.check_authorized(&res)?;
+
+            let what = format!("uploading {:?}", filename);
+            let res_body = read_body_with_progress(&mut res, options, &what).await?;
+            client.release(conn, &res);
+
+            if !res.status().is_success() {
+                let message = String::from_utf8_lossy(&res_body);
+                return Err(Error::Other(format!("Unable to upload {:?}: status {}: {}", filename, res.status(), message.trim())));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! Downloads recorded data from RESOL data logging devices and converts it to CSV.
+//!
+//! Use `sync` to download the log files of a `Host` into a directory and `convert` to turn
+//! them into one CSV file per day. `remove_old_files` removes the files of days no longer needed.
+//! `convert_stream` converts `.vbus` data read from a stream like stdin into CSV instead.
+//!
+//! Use `convert_to_records` or `fetch_records` to decode the data sets in memory instead, e.g. to
+//! store them elsewhere.
+#![deny(warnings)]
+#![deny(future_incompatible)]
+#![deny(nonstandard_style)]
+#![deny(rust_2018_compatibility)]
+#![deny(rust_2018_idioms)]
+#![deny(rust_2021_compatibility)]
+#![deny(unused)]
+
+mod address_filter;
+mod atomic;
+mod checksum;
+mod client;
+mod column_names;
+mod config;
+mod convert;
+mod date_range;
+mod discover;
+mod disk_space;
+mod dump;
+mod error;
+mod field_filter;
+mod filename_pattern;
+mod format;
+mod host;
+mod index;
+mod index_cache;
+mod influx;
+mod live;
+mod lock;
+mod log_file;
+mod manifest;
+mod mqtt;
+mod output;
+mod records;
+mod resample;
+mod retention;
+mod s3;
+mod spec;
+mod sync;
+mod valid_records;
+mod validators;
+mod webhook;
+
+pub use crate::{
+    address_filter::AddressFilter,
+    column_names::ColumnNames,
+    config::{Config, HostConfig},
+    convert::{convert, convert_dir_to_stream, convert_stream, ConvertOptions, ConvertReport, Gap, OutOfOrder},
+    date_range::DateRange,
+    discover::{discover, DiscoveredDevice},
+    dump::dump_raw,
+    error::{Error, Result},
+    field_filter::FieldFilter,
+    filename_pattern::FilenamePattern,
+    format::{parse_timestamp_format, DecimalSeparator, ValueStyle, DEFAULT_TIMESTAMP_FORMAT, RFC3339_TIMESTAMP_FORMAT},
+    host::Host,
+    index::{parse_log_index, parse_next_page_link, LogFileEntry},
+    influx::{upload_to_influx, InfluxTarget},
+    live::{record_live, LiveOptions},
+    lock::DirLock,
+    log_file::{LogFile, LogRotation},
+    manifest::write_manifest,
+    mqtt::MqttOptions,
+    output::{FieldValue, OutputFormat, SourceAddresses},
+    records::{convert_to_records, fetch_records, Record, RecordField},
+    resample::{Resample, ResampleMode},
+    retention::remove_old_files,
+    s3::{upload_to_s3, S3Target},
+    spec::{parse_language, SpecSource, EMBEDDED_SPECIFICATION_FILE},
+    sync::{fetch_log_file, sync, SyncOptions, SyncReport, DEFAULT_USER_AGENT},
+    webhook::{WebhookBatch, WebhookOptions},
+};
+
+pub use resol_vbus::Language;
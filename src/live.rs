@@ -0,0 +1,142 @@
+use std::{fs::{create_dir_all, OpenOptions}, path::Path, time::{Duration, Instant}};
+
+use async_std::{io::BufReader, net::TcpStream};
+use chrono::Utc;
+use futures::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use resol_vbus::{DataSet, LiveDataBuffer, RecordingWriter};
+use tracing::{debug, info, warn};
+
+use crate::{client::with_timeout, convert::LIVE_FILE_SUFFIX, Error, Host, Result};
+
+/// Options for recording live data using the VBus-over-TCP protocol.
+#[derive(Debug, Clone)]
+pub struct LiveOptions {
+    /// The port devices accept VBus-over-TCP connections on.
+    pub port: u16,
+
+    /// The password of the VBus-over-TCP connection, `vbus` unless changed on the device.
+    pub password: String,
+
+    /// The VBus channel to receive on multi-channel devices like the DL3.
+    pub channel: Option<u8>,
+
+    /// Interval in which the latest packets received are written as a data set.
+    pub interval: Duration,
+
+    /// Maximum time to wait for connecting or receiving data, after which the connection is
+    /// considered lost.
+    pub timeout: Duration,
+
+    /// Delay before reconnecting after the connection was lost.
+    pub reconnect_delay: Duration,
+}
+
+impl Default for LiveOptions {
+    fn default() -> LiveOptions {
+        LiveOptions {
+            port: 7053,
+            password: "vbus".to_string(),
+            channel: None,
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(30),
+            reconnect_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Record the live data of a host into `<DATECODE>.live.vbus` files in `dir`, one per UTC day
+/// like the log files downloaded by `sync`, which never touches them, while `convert` processes
+/// them together with the log file of the same day.
+///
+/// Runs until the password is rejected, reconnecting whenever the connection is lost.
+pub async fn record_live(host: &Host, dir: &Path, options: &LiveOptions) -> Result<()> {
+    create_dir_all(dir)?;
+
+    loop {
+        match record_connection(host, dir, options).await {
+            Ok(()) => warn!("Connection to {:?} closed, reconnecting in {:?}", host.name, options.reconnect_delay),
+            Err(err @ Error::Auth(_)) => return Err(err),
+            Err(err) => warn!("Connection to {:?} lost, reconnecting in {:?}: {}", host.name, options.reconnect_delay, err),
+        }
+
+        async_std::task::sleep(options.reconnect_delay).await;
+    }
+}
+
+/// Connect to a host and record its live data until the connection is closed.
+async fn record_connection(host: &Host, dir: &Path, options: &LiveOptions) -> Result<()> {
+    debug!("Connecting to VBus-over-TCP port of {:?}...", host.name);
+
+    let stream = with_timeout(options.timeout, "connecting to VBus-over-TCP port", async { Ok(TcpStream::connect(host.addr_with_port(options.port)).await?) }).await?;
+    let mut reader = BufReader::new(stream.clone());
+    let mut writer = stream;
+
+    expect_reply(&mut reader, host, options).await?;
+
+    let mut commands = vec![format!("PASS {}", options.password)];
+    if let Some(channel) = options.channel {
+        commands.push(format!("CHANNEL {}", channel));
+    }
+    commands.push("DATA".to_string());
+
+    for command in commands {
+        writer.write_all(format!("{}\r\n", command).as_bytes()).await?;
+        if let Err(err) = expect_reply(&mut reader, host, options).await {
+            return Err(if command.starts_with("PASS ") { Error::Auth(host.name.clone()) } else { err });
+        }
+    }
+
+    info!("Recording live data of {:?}", host.name);
+
+    let mut buffer = LiveDataBuffer::new(options.channel.unwrap_or(0));
+    let mut data_set = DataSet::new();
+    let mut next_write = Instant::now() + options.interval;
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        let len = with_timeout(options.timeout, "receiving live data", async { Ok(reader.read(&mut chunk).await?) }).await?;
+        if len == 0 {
+            return Ok(());
+        }
+
+        buffer.extend_from_slice(&chunk [..len]);
+        while let Some(data) = buffer.read_data() {
+            // Only complete packets are recorded, like the log files of the devices contain
+            if data.is_packet() {
+                data_set.add_data(data);
+            }
+        }
+
+        if Instant::now() >= next_write && !data_set.is_empty() {
+            data_set.timestamp = Utc::now();
+            append_data_set(dir, &data_set)?;
+            next_write = Instant::now() + options.interval;
+        }
+    }
+}
+
+/// Read a line sent by the device, which starts with `+` on success and `-` on failure.
+async fn expect_reply(reader: &mut BufReader<TcpStream>, host: &Host, options: &LiveOptions) -> Result<()> {
+    let mut line = String::new();
+    with_timeout(options.timeout, "waiting for reply", async { Ok(reader.read_line(&mut line).await?) }).await?;
+
+    let line = line.trim_end();
+    debug!("Received {:?} from {:?}", line, host.name);
+
+    if line.starts_with('+') {
+        Ok(())
+    } else {
+        Err(format!("Unexpected reply {:?} from {:?}", line, host.name).into())
+    }
+}
+
+/// Append a data set to the `.live.vbus` file of the UTC day it belongs to.
+fn append_data_set(dir: &Path, data_set: &DataSet) -> Result<()> {
+    let filename = dir.join(format!("{}{}", data_set.timestamp.format("%Y%m%d"), LIVE_FILE_SUFFIX));
+
+    debug!("Appending data set with {} packets to {:?}", data_set.len(), filename);
+
+    let file = OpenOptions::new().create(true).append(true).open(filename)?;
+    RecordingWriter::new(file).write_data_set(data_set)?;
+    Ok(())
+}
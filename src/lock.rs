@@ -0,0 +1,41 @@
+use std::{fs::{create_dir_all, File, TryLockError}, path::Path};
+
+use crate::Result;
+
+/// The name of the file locked within a directory.
+const LOCK_FILENAME: &str = ".vbus-sync.lock";
+
+/// An exclusive advisory lock on a directory, held until dropped, so that several instances
+/// syncing or converting into the same directory do not write over each other's files.
+///
+/// The lock is taken on a `.vbus-sync.lock` file within the directory, which is left in place.
+/// The operating system releases it once the process exits, even if it crashed.
+#[derive(Debug)]
+pub struct DirLock {
+    _file: File,
+}
+
+impl DirLock {
+    /// Lock a directory, creating it if needed, or return `None` if another process holds the
+    /// lock.
+    pub fn try_acquire(dir: &Path) -> Result<Option<DirLock>> {
+        let file = open_lock_file(dir)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(DirLock { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+
+    /// Lock a directory, creating it if needed, waiting for another process to release the lock.
+    pub fn acquire(dir: &Path) -> Result<DirLock> {
+        let file = open_lock_file(dir)?;
+        file.lock()?;
+        Ok(DirLock { _file: file })
+    }
+}
+
+fn open_lock_file(dir: &Path) -> std::io::Result<File> {
+    create_dir_all(dir)?;
+    File::options().create(true).truncate(false).write(true).open(dir.join(LOCK_FILENAME))
+}
@@ -0,0 +1,90 @@
+use std::{fs::{File, OpenOptions}, io::{self, Write}, path::{Path, PathBuf}, str::FromStr, sync::{Arc, Mutex}};
+
+use chrono::{DateTime, Utc};
+
+use crate::Result;
+
+/// How often a new log file is started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogRotation {
+    /// Always append to the same file.
+    #[default]
+    Never,
+
+    /// Start a new file every hour, named `<PATH>.<YYYY-MM-DD-HH>`.
+    Hourly,
+
+    /// Start a new file every day, named `<PATH>.<YYYY-MM-DD>`.
+    Daily,
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<LogRotation, String> {
+        match s {
+            "never" => Ok(LogRotation::Never),
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            _ => Err(format!("Unknown log rotation {:?}, expected never, hourly or daily", s)),
+        }
+    }
+}
+
+impl LogRotation {
+    /// The suffix of the file the lines logged at `now` go into, empty if never rotating.
+    fn suffix(&self, now: DateTime<Utc>) -> String {
+        match self {
+            LogRotation::Never => String::new(),
+            LogRotation::Hourly => now.format(".%Y-%m-%d-%H").to_string(),
+            LogRotation::Daily => now.format(".%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+/// A file that log lines are appended to, switching to a new one according to its rotation
+/// based on the current UTC time.
+///
+/// Clones append to the same file, so that one can be handed out per log line.
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    path: PathBuf,
+    rotation: LogRotation,
+    current: Arc<Mutex<(String, File)>>,
+}
+
+impl LogFile {
+    /// Open the log file of the current time, creating it if needed.
+    pub fn open(path: &Path, rotation: LogRotation) -> Result<LogFile> {
+        let suffix = rotation.suffix(Utc::now());
+        let file = open_append(path, &suffix)?;
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            rotation,
+            current: Arc::new(Mutex::new((suffix, file))),
+        })
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut current = self.current.lock().unwrap();
+        let suffix = self.rotation.suffix(Utc::now());
+        if suffix != current.0 {
+            *current = (suffix.clone(), open_append(&self.path, &suffix)?);
+        }
+        // Lines of concurrent writers must not interleave
+        current.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.current.lock().unwrap().1.flush()
+    }
+}
+
+fn open_append(path: &Path, suffix: &str) -> io::Result<File> {
+    let mut filename = path.as_os_str().to_owned();
+    filename.push(suffix);
+    OpenOptions::new().create(true).append(true).open(filename)
+}
@@ -6,341 +6,1040 @@
 #![deny(rust_2021_compatibility)]
 #![deny(unused)]
 
-use std::{fs::{read_dir, File}, collections::HashMap, io::{Read, Write}, path::{Path, PathBuf}};
-use async_std::{fs::create_dir_all, net::TcpStream};
-use chrono::{Utc, TimeZone, DateTime};
-use http_types::{Method, Request, Url};
-use resol_vbus::{Language, Specification, SpecificationFile, RecordingReader};
-use tracing::debug;
-use tracing_subscriber::EnvFilter;
-
-#[derive(Debug)]
-struct Error(String);
+use std::{io::Write, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, Arc}, thread, time::Duration};
+use chrono::{NaiveDate, Utc};
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{bail, WrapErr};
+use futures::{channel::oneshot, future, stream, StreamExt};
+use tracing::{error, info, warn};
+use tracing_subscriber::{fmt::{self, time::ChronoUtc}, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use vbus_sync::{convert, convert_dir_to_stream, convert_stream, discover, dump_raw, parse_language, parse_timestamp_format, record_live, remove_old_files, write_manifest, sync, upload_to_influx, upload_to_s3, AddressFilter, ColumnNames, Config, ConvertOptions, DateRange, DecimalSeparator, DirLock, FieldFilter, FilenamePattern, Host, HostConfig, InfluxTarget, Language, LiveOptions, LogFile, LogRotation, MqttOptions, OutOfOrder, OutputFormat, Resample, ResampleMode, S3Target, SourceAddresses, SpecSource, SyncOptions, ValueStyle, WebhookBatch, WebhookOptions, RFC3339_TIMESTAMP_FORMAT};
+
+/// Downloads recorded data from RESOL data logging devices and converts it to CSV.
+///
+/// Set the `RUST_LOG` environment variable to `debug` to see what happens.
+#[derive(Debug, Parser)]
+#[command(version, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The devices to download from, given as `[http[s]://][USER[:PASS]@]HOST[:PORT]`, or `-` to
+    /// convert the `.vbus` data read from stdin into CSV written to stdout instead.
+    #[arg(required_unless_present_any = ["config", "discover", "sync_discovered"])]
+    hosts: Vec<String>,
+
+    /// List the RESOL devices found on the local network and exit.
+    #[arg(long)]
+    discover: bool,
+
+    /// Also download from the RESOL devices found on the local network.
+    #[arg(long, conflicts_with = "discover")]
+    sync_discovered: bool,
+
+    /// Also download from the hosts listed in this TOML file, using their settings instead of the
+    /// options given here.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Directory to create the per-host directories in.
+    #[arg(short, long, value_name = "DIR", default_value = ".", global = true)]
+    output_dir: PathBuf,
+
+    /// Write the output files of each host into a directory named after it within this one,
+    /// instead of next to its `.vbus` files, e.g. if those are mounted read-only.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["no_convert", "stdout"])]
+    csv_dir: Option<PathBuf>,
+
+    /// Time zone that determines the days and timestamps in the CSV files [default: Europe/Berlin].
+    #[arg(long, value_name = "TZ")]
+    timezone: Option<chrono_tz::Tz>,
+
+    /// Split the CSV files into UTC days with UTC timestamps, same as `--timezone UTC`.
+    #[arg(long, conflicts_with = "timezone")]
+    utc: bool,
+
+    /// Language of the CSV column names (`en`, `de` or `fr`).
+    #[arg(long, value_name = "LANG", default_value = "de", value_parser = parse_language)]
+    language: Language,
+
+    /// Format of the output files, `csv`, `json` for one JSON object per line, `parquet`, `sqlite`
+    /// for a single `data.sqlite` database per host or `influx` for InfluxDB line protocol
+    /// [default: csv].
+    #[arg(long, value_name = "FORMAT")]
+    format: Option<OutputFormat>,
+
+    /// Only convert the fields whose names match this pattern, e.g. `"Temperatur Sensor *"`. `*`
+    /// matches any number of characters, `?` a single one. May be given more than once.
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+
+    /// Do not convert the fields whose names match this pattern. May be given more than once.
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Write exactly the fields of this name as columns, in the order given, with empty values
+    /// where a day lacks them. May be given more than once.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["include", "exclude"])]
+    column: Vec<String>,
+
+    /// Read the names of the columns to write from this file, one per line, before those given
+    /// using `--column`.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["include", "exclude"])]
+    columns_file: Option<PathBuf>,
+
+    /// Read display names replacing the column headers of certain fields from this JSON file,
+    /// like `{"Temperatur Sensor 1": "collector_temperature"}`.
+    #[arg(long, value_name = "FILE")]
+    column_names: Option<PathBuf>,
+
+    /// Header of the column holding the timestamps [default: `Datum`, or `Date` for `en` and `fr`].
+    #[arg(long, value_name = "NAME")]
+    timestamp_column: Option<String>,
+
+    /// Only convert the packets sent by this VBus address, given in hex like `7E11`. May be given
+    /// more than once.
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    source: Vec<u16>,
+
+    /// Only convert the packets sent to this VBus address, given in hex like `0010`. May be given
+    /// more than once.
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    destination: Vec<u16>,
+
+    /// Show the source address of the packet of each field, by prefixing the column `headers`
+    /// with it or by adding a column per packet (`columns`) [default: hidden].
+    #[arg(long, value_name = "MODE")]
+    source_addresses: Option<SourceAddresses>,
+
+    /// Write just the values (`raw`) or append their units (`formatted`) [default: raw].
+    #[arg(long, value_name = "STYLE")]
+    values: Option<ValueStyle>,
+
+    /// Write at most one row per this many seconds, e.g. `60` for one row per minute.
+    #[arg(long, value_name = "SECONDS")]
+    resample: Option<u64>,
+
+    /// Keep the `first` data set of each interval or the `mean` of its numbers [default: first].
+    #[arg(long, value_name = "MODE", requires = "resample")]
+    resample_mode: Option<ResampleMode>,
+
+    /// Only write the rows whose values differ from the previous row, besides the first row of
+    /// each day.
+    #[arg(long)]
+    changes_only: bool,
+
+    /// Omit the columns whose values are the same all day, e.g. fields that are always zero.
+    #[arg(long)]
+    skip_constant: bool,
+
+    /// Also write the file of a day without any data set, holding only the header, instead of
+    /// skipping it.
+    #[arg(long)]
+    write_empty: bool,
+
+    /// Write all days from `--from` to `--to` into a single `<FROM>-<TO>.csv` file instead of one
+    /// file per day.
+    #[arg(long, requires_all = ["from", "to"])]
+    merge: bool,
+
+    /// Write the CSV of the days from `--from` to `--to` of a single host to stdout instead of
+    /// into files, converting them regardless of any files written before.
+    #[arg(long, requires_all = ["from", "to"], conflicts_with_all = ["no_convert", "merge"])]
+    stdout: bool,
+
+    /// Also write a `<DATECODE>_summary.csv` file per day with the minimum, maximum and mean of
+    /// each number.
+    #[arg(long)]
+    summary: bool,
+
+    /// Also write a `<DATECODE>.fields.json` file per output file describing its columns.
+    #[arg(long)]
+    fields_json: bool,
+
+    /// Fail once a day contains more than this many corrupt records, which are skipped otherwise.
+    #[arg(long, value_name = "N")]
+    max_corrupt_records: Option<usize>,
+
+    /// Fail on the first corrupt record, same as `--max-corrupt-records 0`.
+    #[arg(long, conflicts_with = "max_corrupt_records")]
+    strict: bool,
+
+    /// What to do with data sets older than one recorded before them, e.g. after the clock of a
+    /// device jumped backwards: `warn`, `drop` them or `sort` each day [default: warn].
+    #[arg(long, value_name = "MODE")]
+    out_of_order: Option<OutOfOrder>,
+
+    /// Sort the data sets of each day by their timestamps, same as `--out-of-order sort`.
+    #[arg(long, conflicts_with = "out_of_order")]
+    sort: bool,
+
+    /// Only append the data sets newer than the last row of existing CSV files instead of
+    /// writing their days again.
+    #[arg(long, conflicts_with_all = ["merge", "resample", "skip_constant", "summary", "gzip"])]
+    append: bool,
+
+    /// Compress the CSV or JSON files using gzip, writing `<DATECODE>.csv.gz` instead.
+    #[arg(long)]
+    gzip: bool,
+
+    /// Decimal separator of numbers (`point` or `comma`) [default: depends on the language].
+    #[arg(long, value_name = "SEP")]
+    decimal_separator: Option<DecimalSeparator>,
+
+    /// Character between two cells of the CSV files, e.g. `;` or `,` [default: tab].
+    #[arg(long, value_name = "CHAR", value_parser = parse_delimiter)]
+    delimiter: Option<u8>,
+
+    /// Name of the output file of each day without extension, using the placeholders `{date}`,
+    /// `{yyyy}`, `{mm}`, `{dd}` and `{host}`, e.g. `{yyyy}/{mm}/{dd}` [default: {date}].
+    #[arg(long, value_name = "PATTERN")]
+    filename_pattern: Option<FilenamePattern>,
+
+    /// Start the CSV files with a UTF-8 byte order mark, so that Excel detects their encoding.
+    #[arg(long)]
+    bom: bool,
+
+    /// Chrono format string of the timestamps in the first column of the CSV files, e.g.
+    /// `%Y-%m-%dT%H:%M:%S%z` [default: %d.%m.%Y %H:%M:%S].
+    #[arg(long, value_name = "FORMAT", value_parser = parse_timestamp_format)]
+    timestamp_format: Option<String>,
+
+    /// Write the timestamps of the CSV files as RFC 3339 including the offset of the time zone,
+    /// e.g. `2024-04-01T00:00:00+02:00`.
+    #[arg(long, conflicts_with = "timestamp_format")]
+    rfc3339: bool,
+
+    /// POST the converted days to this InfluxDB `/write` endpoint, e.g.
+    /// `http://localhost:8086/write?db=vbus`. Implies `--format influx`.
+    #[arg(long, value_name = "URL")]
+    influx_url: Option<String>,
+
+    /// Upload the converted files to this S3-compatible bucket, given as
+    /// `http[s]://HOST[:PORT]/BUCKET[/PREFIX]`, e.g. `http://localhost:9000/vbus`.
+    #[arg(long, value_name = "URL")]
+    s3_url: Option<String>,
+
+    /// Access key ID to sign the S3 requests with.
+    #[arg(long, value_name = "KEY", requires = "s3_url")]
+    s3_access_key: Option<String>,
+
+    /// Secret access key to sign the S3 requests with.
+    #[arg(long, value_name = "KEY", requires = "s3_url")]
+    s3_secret_key: Option<String>,
+
+    /// Region to sign the S3 requests for [default: us-east-1].
+    #[arg(long, value_name = "REGION", requires = "s3_url")]
+    s3_region: Option<String>,
+
+    /// Also publish every field of the converted data sets to this MQTT broker.
+    #[arg(long, value_name = "HOST")]
+    mqtt_host: Option<String>,
+
+    /// Port of the MQTT broker [default: 1883].
+    #[arg(long, value_name = "PORT", requires = "mqtt_host")]
+    mqtt_port: Option<u16>,
+
+    /// Topic the MQTT topics start with, followed by `/<host>/<device>/<field>` [default: vbus].
+    #[arg(long, value_name = "TOPIC", requires = "mqtt_host")]
+    mqtt_topic: Option<String>,
+
+    /// Also POST the data sets of the converted days as JSON to this URL.
+    #[arg(long, value_name = "URL")]
+    webhook_url: Option<String>,
+
+    /// Header sent with every webhook request, e.g. `"Authorization: Bearer <TOKEN>"`. Can be
+    /// given multiple times.
+    #[arg(long, value_name = "HEADER", value_parser = parse_header, requires = "webhook_url")]
+    webhook_header: Vec<(String, String)>,
+
+    /// Send all data sets of a day in one webhook request (`day`) or one request per data set
+    /// (`data-set`) [default: day].
+    #[arg(long, value_name = "BATCH", requires = "webhook_url")]
+    webhook_batch: Option<WebhookBatch>,
+
+    /// Use this VBus specification file instead of the embedded one, e.g. for newer devices.
+    #[arg(long, value_name = "FILE", global = true)]
+    spec: Option<PathBuf>,
+
+    /// Only sync and convert the days starting at this date (`YYYY-MM-DD` or `YYYYMMDD`).
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    from: Option<NaiveDate>,
+
+    /// Only sync and convert the days up to and including this date (`YYYY-MM-DD` or `YYYYMMDD`).
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    to: Option<NaiveDate>,
+
+    /// Do not download anything, only convert the log files already in the host directories.
+    #[arg(long)]
+    offline: bool,
+
+    /// Only download the log files, without converting them.
+    #[arg(long, conflicts_with = "offline")]
+    no_convert: bool,
+
+    /// Connect to all hosts using TLS, even if given without `https://` scheme.
+    #[arg(long)]
+    tls: bool,
+
+    /// Accept self-signed or otherwise invalid TLS certificates.
+    #[arg(long)]
+    insecure: bool,
+
+    /// Username for HTTP Basic Auth, unless given as part of the host.
+    #[arg(long, value_name = "USER")]
+    user: Option<String>,
+
+    /// Password for HTTP Basic Auth, unless given as part of the host.
+    #[arg(long, value_name = "PASSWORD")]
+    password: Option<String>,
+
+    /// Number of times a failed request is retried [default: 3].
+    #[arg(long, value_name = "N")]
+    retries: Option<u32>,
+
+    /// Seconds to wait for a connection, a response or a response body [default: 30].
+    #[arg(long, value_name = "SECONDS", global = true)]
+    timeout: Option<u64>,
+
+    /// Maximum number of log files downloaded simultaneously from a host [default: 2].
+    #[arg(long, value_name = "N")]
+    concurrency: Option<usize>,
+
+    /// Maximum number of requests per second sent to a host, e.g. to spare older devices.
+    #[arg(long, value_name = "N", value_parser = parse_request_rate)]
+    max_request_rate: Option<f64>,
+
+    /// Log the periods longer than this many seconds without any data set within the converted
+    /// days, e.g. while a device was offline.
+    #[arg(long, value_name = "SECONDS")]
+    gap_threshold: Option<u64>,
+
+    /// Also write the gaps found into a `<DATECODE>_gaps.json` file per day.
+    #[arg(long, requires = "gap_threshold")]
+    gaps_json: bool,
+
+    /// Only log which log files would be downloaded and which output files would be written,
+    /// without changing anything.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Do not store a log file unless this many megabytes remain free on the disk afterwards.
+    #[arg(long, value_name = "MB")]
+    min_free_space: Option<u64>,
+
+    /// Only read this many pages of a log directory index that the device splits into several.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_index_pages: Option<u32>,
+
+    /// `User-Agent` header sent with every request [default: vbus-sync/<VERSION>].
+    #[arg(long, value_name = "AGENT")]
+    user_agent: Option<String>,
+
+    /// Check that the log files are valid VBus recordings instead of only comparing their size,
+    /// downloading them again otherwise.
+    #[arg(long)]
+    verify: bool,
+
+    /// Remove the log files of the days more than this many days ago from the host directories
+    /// after converting, and no longer sync or convert those days.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    keep_days: Option<u32>,
+
+    /// Also remove the output files of the days removed by `--keep-days`, in any format.
+    #[arg(long, requires = "keep_days")]
+    remove_csv: bool,
+
+    /// Describe the `.vbus` files of each host in a `manifest.json` file in its directory after
+    /// syncing and converting.
+    #[arg(long)]
+    manifest: bool,
+
+    /// Wait for another instance syncing or converting into the directory of a host to finish,
+    /// instead of skipping that host.
+    #[arg(long)]
+    wait_for_lock: bool,
+
+    /// Log human readable lines (`human`) or one JSON object per line (`json`), e.g. for a log
+    /// aggregator. Also set by the `VBUS_SYNC_LOG_FORMAT` environment variable [default: human].
+    #[arg(long, value_name = "FORMAT", value_parser = ["human", "json"], global = true)]
+    log_format: Option<String>,
+
+    /// Also append the log to this file, e.g. when running from cron.
+    #[arg(long, value_name = "FILE", global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Start a new log file `never`, `hourly` or `daily`, appending the hour or day to its name
+    /// [default: never].
+    #[arg(long, value_name = "ROTATION", requires = "log_file", global = true)]
+    log_rotation: Option<LogRotation>,
+
+    /// Maximum number of hosts synced and converted simultaneously [default: 4].
+    #[arg(long, value_name = "N")]
+    parallel_hosts: Option<usize>,
+}
 
-impl From<String> for Error {
-    fn from(other: String) -> Error {
-        Error(other)
-    }
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the version of the tool and the VBus specification in use, then exit.
+    Info,
+
+    /// Print each packet recorded in the given `.vbus` files as a line with its timestamp, channel
+    /// and raw bytes in hex, without decoding it, then exit.
+    Dump {
+        /// The `.vbus` files to dump.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Record the live data of the devices using the VBus-over-TCP protocol until interrupted,
+    /// appending it to `.live.vbus` files in the per-host directories.
+    Live {
+        /// The devices to record, given as their host name or IP address.
+        #[arg(required = true)]
+        hosts: Vec<String>,
+
+        /// Password of the VBus-over-TCP connection [default: vbus].
+        #[arg(long, value_name = "PASSWORD")]
+        vbus_password: Option<String>,
+
+        /// VBus channel to record on multi-channel devices like the DL3.
+        #[arg(long, value_name = "N")]
+        channel: Option<u8>,
+
+        /// Seconds between two data sets written [default: 60].
+        #[arg(long, value_name = "SECONDS")]
+        interval: Option<u64>,
+    },
 }
 
-impl From<&str> for Error {
-    fn from(other: &str) -> Error {
-        Error(other.to_string())
+fn parse_delimiter(arg: &str) -> Result<u8, String> {
+    match arg.as_bytes() {
+        b"tab" | b"\\t" => Ok(b'\t'),
+        [b'"' | b'\n' | b'\r'] => Err(format!("Invalid delimiter {:?}", arg)),
+        [delimiter] if delimiter.is_ascii() => Ok(*delimiter),
+        _ => Err(format!("Invalid delimiter {:?}, expected a single ASCII character or tab", arg)),
     }
 }
 
-trait IntoError: std::fmt::Debug {}
+fn parse_address(arg: &str) -> Result<u16, String> {
+    let digits = arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")).unwrap_or(arg);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid VBus address {:?}, expected hex like 7E11", arg))
+}
 
-impl<T: IntoError> From<T> for Error {
-    fn from(other: T) -> Error {
-        Error(format!("{:?}", other))
+fn parse_request_rate(arg: &str) -> Result<f64, String> {
+    match arg.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("Invalid request rate {:?}, expected a positive number like 0.5", arg)),
     }
 }
 
-type Result<T> = std::result::Result<T, Error>;
+/// Parse an HTTP header given as `Name: value`.
+fn parse_header(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() && !name.contains(char::is_whitespace) => Ok((name.to_string(), value.trim().to_string())),
+        _ => Err(format!("Invalid header {:?}, expected NAME: VALUE", arg)),
+    }
+}
 
-impl IntoError for std::io::Error {}
-impl IntoError for std::num::ParseIntError {}
-impl IntoError for chrono::ParseError {}
-impl IntoError for color_eyre::Report {}
-impl IntoError for http_types::Error {}
-impl IntoError for http_types::url::ParseError {}
-impl IntoError for resol_vbus::Error {}
+/// Parse a date given either as `YYYY-MM-DD` or as a `YYYYMMDD` datecode like the log files use.
+fn parse_date(arg: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(arg, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(arg, "%Y%m%d"))
+        .map_err(|_| format!("Invalid date {:?}, expected YYYY-MM-DD or YYYYMMDD", arg))
+}
 
 #[async_std::main]
-async fn main() -> Result<()> {
-    setup_debugging()?;
-
-    let spec_file_bytes = include_bytes!("../vbus_specification.vsf");
-    let spec_file = SpecificationFile::from_bytes(spec_file_bytes)?;
-    let spec = Specification::from_file(spec_file, Language::De);
+async fn main() -> color_eyre::Result<()> {
+    let mut cli = Cli::parse();
 
-    for arg in std::env::args().skip(1) {
-        sync_and_convert(&arg, &spec).await?;
+    let log_format = match &cli.log_format {
+        Some(log_format) => log_format.clone(),
+        None => std::env::var("VBUS_SYNC_LOG_FORMAT").unwrap_or_default(),
+    };
+    let log_file = match &cli.log_file {
+        Some(filename) => Some(LogFile::open(filename, cli.log_rotation.unwrap_or_default()).wrap_err_with(|| format!("Unable to open log file {:?}", filename))?),
+        None => None,
+    };
+    // Keep stdout free for the CSV when converting stdin
+    let from_stdin = cli.hosts == ["-"];
+    setup_debugging(log_format == "json", log_file, from_stdin || cli.stdout)?;
+
+    // Needs no specification, so that it also works with packets it does not know
+    if let Some(Command::Dump { files }) = &cli.command {
+        dump_raw(files, &mut std::io::stdout().lock()).wrap_err("Unable to dump log files")?;
+        return Ok(());
     }
 
-    Ok(())
-}
-
-async fn sync_and_convert(host: &str, spec: &Specification) -> Result<()> {
-    debug!("Downloading log file index for {:?}", host);
-
-    let addr = format!("{}:80", host);
-    let stream = TcpStream::connect(&addr).await?;
+    let spec = match &cli.spec {
+        Some(filename) => SpecSource::from_file(filename, cli.language).wrap_err_with(|| format!("Unable to read specification file {:?}", filename))?,
+        None => SpecSource::embedded(cli.language),
+    };
 
-    let url = format!("http://{}/log/", host);
-    let url = Url::parse(&url)?;
+    // Load once up front so that a broken specification is reported before syncing
+    spec.load().wrap_err("Unable to load specification")?;
 
-    let req = Request::new(Method::Get, url);
-    let mut res = async_h1::connect(stream.clone(), req).await?;
+    if let Some(Command::Info) = &cli.command {
+        let source = match &cli.spec {
+            Some(filename) => format!("{:?}", filename),
+            None => "embedded".to_string(),
+        };
+        println!("vbus-sync {}", env!("CARGO_PKG_VERSION"));
+        println!("VBus specification: {}, dated {}", source, spec.datecode()?);
+        return Ok(());
+    }
 
-    if !res.status().is_success() {
-        return Err("Unable to download log directory index".into());
+    let date_range = DateRange {
+        from: cli.from,
+        to: cli.to,
+    };
+    if let (Some(from), Some(to)) = (date_range.from, date_range.to) {
+        if from > to {
+            bail!("--from {} is after --to {}", from, to);
+        }
     }
 
-    let body = res.body_string().await?;
+    // The days before the cutoff are removed, so they must not be downloaded or converted again
+    let keep_since = cli.keep_days.map(|keep_days| Utc::now().date_naive() - chrono::Duration::days(keep_days.into()));
+    let starting_no_earlier_than = |first_day: Option<NaiveDate>| match first_day {
+        Some(first_day) => DateRange {
+            from: Some(date_range.from.map_or(first_day, |from| from.max(first_day))),
+            ..date_range
+        },
+        None => date_range,
+    };
 
-    // debug!(%body);
+    let interrupted = Arc::new(AtomicBool::new(false));
+
+    let mut options = SyncOptions {
+        tls: cli.tls,
+        accept_invalid_certs: cli.insecure,
+        username: cli.user,
+        password: cli.password,
+        // Syncing includes the day before the range
+        date_range: starting_no_earlier_than(keep_since.and_then(|keep_since| keep_since.succ_opt())),
+        verify: cli.verify,
+        dry_run: cli.dry_run,
+        min_free_space: cli.min_free_space.unwrap_or(0).saturating_mul(1024 * 1024),
+        max_index_pages: cli.max_index_pages.map(|max_index_pages| max_index_pages as usize),
+        interrupted: interrupted.clone(),
+        ..SyncOptions::default()
+    };
+    if let Some(retries) = cli.retries {
+        options.retries = retries;
+    }
+    if let Some(timeout) = cli.timeout {
+        options.timeout = Duration::from_secs(timeout);
+    }
+    if let Some(concurrency) = cli.concurrency {
+        options.concurrency = concurrency;
+    }
+    if let Some(max_request_rate) = cli.max_request_rate {
+        options.max_request_rate = Some(max_request_rate);
+    }
+    if let Some(user_agent) = cli.user_agent {
+        options.user_agent = user_agent;
+    }
 
-    create_dir_all(host).await?;
+    let mut columns = match &cli.columns_file {
+        Some(filename) => {
+            let contents = std::fs::read_to_string(filename).wrap_err_with(|| format!("Unable to read columns file {:?}", filename))?;
+            contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_string).collect()
+        }
+        None => Vec::new(),
+    };
+    columns.extend(cli.column);
+
+    let mut convert_options = ConvertOptions {
+        date_range: starting_no_earlier_than(keep_since),
+        decimal_separator: cli.decimal_separator,
+        field_filter: FieldFilter {
+            include: cli.include,
+            exclude: cli.exclude,
+        },
+        columns,
+        source_addresses: cli.source_addresses.unwrap_or_default(),
+        timestamp_column: cli.timestamp_column,
+        address_filter: AddressFilter {
+            sources: cli.source,
+            destinations: cli.destination,
+        },
+        value_style: cli.values.unwrap_or_default(),
+        gzip: cli.gzip,
+        filename_pattern: cli.filename_pattern.unwrap_or_default(),
+        bom: cli.bom,
+        resample: cli.resample.map(|interval| Resample {
+            interval: Duration::from_secs(interval),
+            mode: cli.resample_mode.unwrap_or_default(),
+        }),
+        changes_only: cli.changes_only,
+        summary: cli.summary,
+        fields_json: cli.fields_json,
+        append: cli.append,
+        max_corrupt_records: if cli.strict { Some(0) } else { cli.max_corrupt_records },
+        out_of_order: if cli.sort { OutOfOrder::Sort } else { cli.out_of_order.unwrap_or_default() },
+        skip_constant: cli.skip_constant,
+        write_empty: cli.write_empty,
+        merge: cli.merge,
+        gap_threshold: cli.gap_threshold.map(Duration::from_secs),
+        gaps_json: cli.gaps_json,
+        dry_run: cli.dry_run,
+        interrupted: interrupted.clone(),
+        ..ConvertOptions::default()
+    };
+    if let Some(timezone) = cli.timezone {
+        convert_options.timezone = timezone;
+    } else if cli.utc {
+        convert_options.timezone = chrono_tz::UTC;
+    }
+    if let Some(format) = cli.format {
+        convert_options.output_format = format;
+    }
+    if let Some(filename) = &cli.column_names {
+        convert_options.column_names = ColumnNames::from_file(filename).wrap_err_with(|| format!("Unable to read column names file {:?}", filename))?;
+    }
 
-    for (idx, _) in body.match_indices("<a href=") {
-        let start_idx = if &body [idx + 8..idx + 14] == "'/log/" {
-            Some(idx + 14)
-        } else if &body [idx + 8..idx + 9] == "\"" {
-            Some(idx + 9)
-        } else {
-            None
+    if let Some(mqtt_host) = cli.mqtt_host {
+        let mut mqtt_options = MqttOptions {
+            host: mqtt_host,
+            ..MqttOptions::default()
         };
+        if let Some(port) = cli.mqtt_port {
+            mqtt_options.port = port;
+        }
+        if let Some(topic) = cli.mqtt_topic {
+            mqtt_options.base_topic = topic;
+        }
+        if let Some(timeout) = cli.timeout {
+            mqtt_options.timeout = Duration::from_secs(timeout);
+        }
+        convert_options.mqtt = Some(mqtt_options);
+    }
 
-        if let Some(start_idx) = start_idx {
-            let mid_idx = start_idx + 8;
-            let end_idx = start_idx + 21;
-
-            if end_idx <= body.len() {
-                let suffix = &body [mid_idx..end_idx];
-                if suffix == "_packets.vbus" {
-                    let datecode = &body [start_idx..mid_idx];
+    if let Some(webhook_url) = cli.webhook_url {
+        let mut webhook_options = WebhookOptions {
+            url: webhook_url,
+            headers: cli.webhook_header,
+            ..WebhookOptions::default()
+        };
+        if let Some(batch) = cli.webhook_batch {
+            webhook_options.batch = batch;
+        }
+        if let Some(timeout) = cli.timeout {
+            webhook_options.timeout = Duration::from_secs(timeout);
+        }
+        convert_options.webhook = Some(webhook_options);
+    }
 
-                    sync_for_datecode(host, datecode).await?;
-                }
+    let influx_target = match &cli.influx_url {
+        Some(url) => {
+            match cli.format {
+                None | Some(OutputFormat::Influx) => convert_options.output_format = OutputFormat::Influx,
+                Some(_) => bail!("--influx-url requires --format influx"),
+            }
+            Some(InfluxTarget::parse(url)?)
+        }
+        None => None,
+    };
+    let s3_target = match &cli.s3_url {
+        Some(url) => {
+            let mut s3_target = S3Target::parse(url)?;
+            s3_target.access_key = cli.s3_access_key.clone().unwrap_or_default();
+            s3_target.secret_key = cli.s3_secret_key.clone().unwrap_or_default();
+            if let Some(region) = &cli.s3_region {
+                s3_target.region = region.clone();
             }
+            Some(s3_target)
         }
+        None => None,
+    };
+    if let Some(delimiter) = cli.delimiter {
+        convert_options.delimiter = delimiter;
+    }
+    if let Some(timestamp_format) = cli.timestamp_format {
+        convert_options.timestamp_format = timestamp_format;
+    } else if cli.rfc3339 {
+        convert_options.timestamp_format = RFC3339_TIMESTAMP_FORMAT.to_string();
     }
 
-    convert(host, spec)?;
-
-    Ok(())
-}
-
-async fn sync_for_datecode(host: &str, datecode: &str) -> Result<()> {
-    debug!("Fetching information about log file dated {}", datecode);
-
-    let vbus_filename = format!("{}/{}.vbus", host, datecode);
-
-    let addr = format!("{}:80", host);
-    let stream = TcpStream::connect(&addr).await?;
+    if from_stdin {
+        let written = convert_stream(&mut std::io::stdin().lock(), Box::new(std::io::stdout()), &spec, &convert_options).wrap_err("Unable to convert stdin")?;
+        if !written {
+            warn!("No data sets within the date range");
+        }
+        return Ok(());
+    }
 
-    let url = format!("http://{}/log/{}_packets.vbus", host, datecode);
-    let url = Url::parse(&url)?;
+    if let Some(Command::Live { hosts, vbus_password, channel, interval }) = &cli.command {
+        let mut live_options = LiveOptions {
+            channel: *channel,
+            ..LiveOptions::default()
+        };
+        if let Some(password) = vbus_password {
+            live_options.password = password.clone();
+        }
+        if let Some(interval) = interval {
+            live_options.interval = Duration::from_secs(*interval);
+        }
+        if let Some(timeout) = cli.timeout {
+            live_options.timeout = Duration::from_secs(timeout);
+        }
 
-    let req = Request::new(Method::Head, url);
-    let res = async_h1::connect(stream.clone(), req).await?;
+        let hosts = hosts.iter().map(|arg| Host::parse(arg, &options)).collect::<Result<Vec<_>, _>>()?;
+
+        // Recording only stops once the password of a host was rejected
+        let live_options = &live_options;
+        let output_dir = &cli.output_dir;
+        future::join_all(hosts.iter().map(|host| async move {
+            let dir = output_dir.join(host.dir_name());
+            // Held while recording, like while syncing and converting
+            let _locks = match lock_dirs(vec![dir.clone()], false).await {
+                Ok(Some(locks)) => locks,
+                Ok(None) => {
+                    error!("{}: another instance is using {:?}", host.name, dir);
+                    return;
+                }
+                Err(err) => {
+                    error!("{}: {}", host.name, err);
+                    return;
+                }
+            };
+            if let Err(err) = record_live(host, &dir, live_options).await {
+                error!("{}: {}", host.name, err);
+            }
+        })).await;
 
-    if !res.status().is_success() {
-        return Err(format!("Unable to download log file dated {}", datecode).into());
+        bail!("Unable to record any of the hosts");
     }
 
-    // debug!(?res);
-
-    let content_length = if let Some(content_length) = res.header("content-length") {
-        content_length.as_str().parse::<u64>()?
-    } else {
-        return Err(format!("Unable to determine file size dated {}", datecode).into());
-    };
+    // Recording live data is stopped right away, since every data set is appended on its own
+    {
+        let interrupted = interrupted.clone();
+        ctrlc::set_handler(move || {
+            if interrupted.swap(true, Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+            warn!("Interrupted, finishing the files being written. Press Ctrl-C again to exit immediately.");
+        })?;
+    }
 
-    // debug!(?content_length);
+    if cli.discover || cli.sync_discovered {
+        let devices = discover(&options).await.wrap_err("Unable to discover devices")?;
+        info!("Discovered {} devices", devices.len());
+        if cli.discover {
+            for device in &devices {
+                println!("{}\t{}\t{}", device.address, device.product.as_deref().unwrap_or("-"), device.name.as_deref().unwrap_or("-"));
+            }
+            return Ok(());
+        }
+        cli.hosts.extend(devices.iter().map(|device| device.address.to_string()));
+    }
 
-    let file_size = if let Ok(metadata) = std::fs::metadata(&vbus_filename) {
-        metadata.len()
-    } else {
-        0
+    let config = match &cli.config {
+        Some(filename) => Config::from_file(filename).wrap_err_with(|| format!("Unable to read config file {:?}", filename))?,
+        None => Config::default(),
     };
 
-    let needs_download = file_size != content_length;
+    let spec = Arc::new(spec);
+    let convert_options = Arc::new(convert_options);
+
+    // Parse all hosts up front, so that a typo is reported before syncing any of them
+    let mut jobs = Vec::new();
+    for arg in &cli.hosts {
+        let host = Host::parse(arg, &options)?;
+        jobs.push(Job {
+            dir: cli.output_dir.join(host.dir_name()),
+            host,
+            spec: spec.clone(),
+            convert_options: convert_options.clone(),
+        });
+    }
+    for host_config in &config.hosts {
+        jobs.push(Job::from_config(host_config, &cli.output_dir, &options, &spec, &convert_options)?);
+    }
 
-    // debug!(?needs_download);
+    if let Some(csv_dir) = &cli.csv_dir {
+        for job in &mut jobs {
+            job.convert_options = Arc::new(ConvertOptions {
+                output_dir: Some(csv_dir.join(job.host.dir_name())),
+                ..ConvertOptions::clone(&job.convert_options)
+            });
+        }
+    }
 
-    if needs_download {
-        let url = format!("http://{}/log/{}_packets.vbus", host, datecode);
-        let url = Url::parse(&url)?;
+    if cli.stdout && jobs.len() != 1 {
+        bail!("--stdout requires a single host, got {}", jobs.len());
+    }
 
-        let req = Request::new(Method::Get, url);
-        let mut res = async_h1::connect(stream.clone(), req).await?;
+    let run = Run {
+        offline: cli.offline,
+        no_convert: cli.no_convert,
+        stdout: cli.stdout,
+        wait_for_lock: cli.wait_for_lock,
+        keep_since,
+        remove_csv: cli.remove_csv,
+        manifest: cli.manifest,
+        options,
+        influx_target,
+        s3_target,
+    };
 
-        if !res.status().is_success() {
-            return Err(format!("Unable to download log file dated {}", datecode).into());
+    let run = &run;
+    let results = stream::iter(&jobs)
+        .map(|job| async move { (job, run.sync_and_convert(job).await) })
+        .buffer_unordered(cli.parallel_hosts.unwrap_or(4).max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut error_count = 0;
+    for (job, result) in results {
+        if let Err(err) = result {
+            error!("{}: {:#}", job.host.name, err);
+            error_count += 1;
         }
+    }
 
-        let body = res.body_bytes().await?;
+    if error_count > 0 {
+        bail!("Unable to sync {} of {} hosts", error_count, jobs.len());
+    }
 
-        async_std::fs::write(&vbus_filename, &body).await?;
-    } else {
-        debug!("Skipping download for file dated {}", datecode);
-    };
+    if interrupted.load(Ordering::SeqCst) {
+        bail!("Interrupted, run again to sync and convert the remaining files");
+    }
 
     Ok(())
 }
 
-fn parse_datecode<Tz: TimeZone>(datecode_str: &str, tz: &Tz) -> Result<DateTime<Tz>> {
-    let datecode = datecode_str.parse::<u32>()?;
-    let year = (datecode / 10000) as i32;
-    let month = (datecode / 100) % 100;
-    let day = datecode % 100;
-    let dt = tz.ymd(year, month, day).and_hms(0, 0, 0);
-    Ok(dt)
+/// A host to sync and convert, along with the settings that may differ between hosts.
+struct Job {
+    host: Host,
+    dir: PathBuf,
+    spec: Arc<SpecSource>,
+    convert_options: Arc<ConvertOptions>,
 }
 
-fn convert(host: &str, spec: &Specification) -> Result<()> {
-    let mut all_vbus_filenames = Vec::new();
-    let mut vbus_file_modified_by_rel_filename = HashMap::new();
-    let mut csv_file_modified_by_rel_filename = HashMap::new();
-
-    for entry in read_dir(host)? {
-        let entry = entry?;
-
-        if !entry.file_type()?.is_file() {
-            // nop
-        } else {
-            let filename = entry.file_name().to_string_lossy().to_string();
-            if !filename [0..8].chars().all(|c| char::is_digit(c, 10)) {
-                // nop
-            } else if (filename.len() == 13) && filename.ends_with(".vbus") {
-                all_vbus_filenames.push(filename.clone());
-                vbus_file_modified_by_rel_filename.insert(filename, entry.metadata()?.modified()?);
-            } else if (filename.len() == 12) && filename.ends_with(".csv") {
-                csv_file_modified_by_rel_filename.insert(filename, entry.metadata()?.modified()?);
-            }
+impl Job {
+    /// Apply the settings of a host from the config file on top of the command line options.
+    fn from_config(host_config: &HostConfig, output_dir: &Path, options: &SyncOptions, spec: &Arc<SpecSource>, convert_options: &Arc<ConvertOptions>) -> color_eyre::Result<Job> {
+        let mut options = options.clone();
+        if let Some(username) = &host_config.username {
+            options.username = Some(username.clone());
         }
-    }
-
-    all_vbus_filenames.sort();
-
-    let tz = chrono_tz::Europe::Berlin;
-
-    let mut local_to_utc_datecodes = HashMap::new();
+        if let Some(password) = &host_config.password {
+            options.password = Some(password.clone());
+        }
+        let host = Host::parse(&host_config.host, &options)?;
 
-    for vbus_filename in &all_vbus_filenames {
-        let datecode_str_utc = vbus_filename [0..8].to_string();
+        let output_dir = host_config.output_dir.as_deref().unwrap_or(output_dir);
 
-        let start_of_day_utc = parse_datecode(&datecode_str_utc, &Utc)?;
-        let end_of_day_utc = start_of_day_utc.date().and_hms(23, 59, 59);
+        let spec = match host_config.language {
+            Some(language) => Arc::new(spec.with_language(language)),
+            None => spec.clone(),
+        };
 
-        let start_of_day_local = start_of_day_utc.with_timezone(&tz);
-        let end_of_day_local = end_of_day_utc.with_timezone(&tz);
+        let convert_options = match host_config.timezone {
+            Some(timezone) => Arc::new(ConvertOptions {
+                timezone,
+                ..ConvertOptions::clone(convert_options)
+            }),
+            None => convert_options.clone(),
+        };
 
-        let start_of_day_local_datecode = start_of_day_local.format("%Y%m%d").to_string();
-        let end_of_day_local_datecode = end_of_day_local.format("%Y%m%d").to_string();
+        Ok(Job {
+            dir: output_dir.join(host.dir_name()),
+            host,
+            spec,
+            convert_options,
+        })
+    }
 
-        if !local_to_utc_datecodes.contains_key(&start_of_day_local_datecode) {
-            local_to_utc_datecodes.insert(start_of_day_local_datecode.clone(), Vec::new());
-        }
-        local_to_utc_datecodes.get_mut(&start_of_day_local_datecode).unwrap().push(datecode_str_utc.clone());
+    /// The directory the output files of the host are written into.
+    fn output_dir(&self) -> &Path {
+        self.convert_options.output_dir.as_deref().unwrap_or(&self.dir)
+    }
+}
 
-        if !local_to_utc_datecodes.contains_key(&end_of_day_local_datecode) {
-            local_to_utc_datecodes.insert(end_of_day_local_datecode.clone(), Vec::new());
+/// Lock the directories of a host in the order given, so that other instances do not write into
+/// them at the same time.
+///
+/// Unless waiting for other instances to release them, returns `None` if any of them is locked.
+async fn lock_dirs(dirs: Vec<PathBuf>, wait: bool) -> color_eyre::Result<Option<Vec<DirLock>>> {
+    if wait {
+        // Waiting blocks, so move it to its own thread to keep syncing the other hosts
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || {
+            let _ = sender.send(dirs.iter().map(|dir| DirLock::acquire(dir)).collect::<Result<Vec<_>, _>>());
+        });
+        Ok(Some(receiver.await??))
+    } else {
+        let mut locks = Vec::new();
+        for dir in &dirs {
+            match DirLock::try_acquire(dir)? {
+                Some(lock) => locks.push(lock),
+                None => return Ok(None),
+            }
         }
-        local_to_utc_datecodes.get_mut(&end_of_day_local_datecode).unwrap().push(datecode_str_utc.clone());
+        Ok(Some(locks))
     }
+}
 
-    for (csv_datecode, mut vbus_datecodes) in local_to_utc_datecodes {
-        let rel_csv_filename = format!("{}.csv", &csv_datecode);
-        let csv_filename = format!("{}/{}", host, &rel_csv_filename);
-        let csv_filename = Path::new(&csv_filename);
-
-        vbus_datecodes.sort();
-
-        let csv_modified = csv_file_modified_by_rel_filename.get(&rel_csv_filename);
-
-        let mut vbus_filenames = Vec::new();
-        let mut needs_conversion = csv_modified.is_none();
-        for vbus_datecode in &vbus_datecodes {
-            let rel_vbus_filename = format!("{}.vbus", &vbus_datecode);
-            if let Some(vbus_modified) = vbus_file_modified_by_rel_filename.get(&rel_vbus_filename) {
-                let vbus_filename = format!("{}/{}", host, rel_vbus_filename);
-                let vbus_filename = PathBuf::from(&vbus_filename);
+/// The settings shared by all hosts.
+struct Run {
+    offline: bool,
+    no_convert: bool,
+    stdout: bool,
+    wait_for_lock: bool,
+    keep_since: Option<NaiveDate>,
+    remove_csv: bool,
+    manifest: bool,
+    options: SyncOptions,
+    influx_target: Option<InfluxTarget>,
+    s3_target: Option<S3Target>,
+}
 
-                vbus_filenames.push(vbus_filename);
+impl Run {
+    /// Download the log files of a host into its directory and convert them, as configured.
+    async fn sync_and_convert(&self, job: &Job) -> color_eyre::Result<()> {
+        let Job { host, dir, .. } = job;
+        if self.options.interrupted.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if self.offline && !dir.is_dir() {
+            bail!("Directory {:?} does not exist, unable to convert offline", dir);
+        }
 
-                if !needs_conversion {
-                    if *vbus_modified > *csv_modified.unwrap() {
-                        needs_conversion = true;
-                    }
+        // Held until the host is done, a dry run does not change any files
+        let mut dirs = vec![dir.clone()];
+        if job.output_dir() != dir {
+            dirs.push(job.output_dir().to_path_buf());
+        }
+        let _locks = if self.options.dry_run {
+            Vec::new()
+        } else {
+            match lock_dirs(dirs, self.wait_for_lock).await? {
+                Some(locks) => locks,
+                None => {
+                    warn!("{}: skipping, since another instance is using {:?}", host.name, dir);
+                    return Ok(());
                 }
             }
-        }
-
-        if needs_conversion {
-            debug!("Converting {:?} into {:?}...", &vbus_filenames, &csv_filename);
-
-            let start_of_day_local = parse_datecode(&csv_datecode, &tz)?;
-            let end_of_day_local = start_of_day_local.date().and_hms(23, 59, 59);
-
-            let start_of_day_utc = start_of_day_local.with_timezone(&Utc);
-            let end_of_day_utc = end_of_day_local.with_timezone(&Utc);
+        };
 
-            let mut vbus_bytes = Vec::new();
-            for vbus_filename in &vbus_filenames {
-                let mut vbus_file = File::open(vbus_filename)?;
-                vbus_file.read_to_end(&mut vbus_bytes)?;
+        if !self.offline {
+            let report = sync(host, dir, &self.options).await?;
+            info!("{}: checked {} log files, downloaded {} ({} bytes)", host.name, report.available, report.downloaded.len(), report.bytes_downloaded);
+            if report.interrupted {
+                return Ok(());
             }
+        }
+        if self.stdout {
+            self.convert_to_stdout(job).await?;
+        } else if !self.no_convert {
+            self.convert(job).await?;
+        }
 
-            let mut rr = RecordingReader::new(vbus_bytes.as_slice());
-            rr.set_min_max_timestamps(Some(start_of_day_utc.clone()), Some(end_of_day_utc.clone()));
-
-            let topo_data_set = rr.read_topology_data_set()?;
-
-            let mut output_buffer = Vec::new();
-            let output = &mut output_buffer;
-
-            write!(output, "Datum")?;
-
-            for field in spec.fields_in_data_set(&topo_data_set) {
-                let name = &field.field_spec().name;
-                let unit_text = field.field_spec().unit_text.trim();
-                if unit_text.len() > 0 {
-                    write!(output, "\t{} [{}]", name, unit_text)?;
-                } else {
-                    write!(output, "\t{}", name)?;
-                }
+        if let Some(keep_since) = self.keep_since {
+            if self.options.interrupted.load(Ordering::SeqCst) {
+                return Ok(());
             }
+            let output_files = self.remove_csv.then_some(&job.convert_options.filename_pattern);
+            let mut removed = remove_old_files(dir, keep_since, output_files, self.options.dry_run)?;
+            if self.remove_csv && job.output_dir() != dir {
+                removed.extend(remove_old_files(job.output_dir(), keep_since, output_files, self.options.dry_run)?);
+            }
+            if !removed.is_empty() {
+                info!("{}: removed {} files of the days before {}", host.name, removed.len(), keep_since);
+            }
+        }
 
-            write!(output, "\n")?;
-
-            let mut rr = RecordingReader::new(vbus_bytes.as_slice());
-            rr.set_min_max_timestamps(Some(start_of_day_utc), Some(end_of_day_utc));
-
-            let mut contains_data_lines = false;
-            while let Some(rr_data_set) = rr.read_data_set()? {
-                let mut data_set = topo_data_set.clone();
-                data_set.timestamp = rr_data_set.timestamp;
-                data_set.add_data_set(rr_data_set);
-
-                let local_now = data_set.timestamp.with_timezone(&tz);
-
-                write!(output, "{}", local_now.format("%d.%m.%Y %H:%M:%S"))?;
+        // A dry run does not change any files
+        if self.manifest && !self.options.dry_run {
+            write_manifest(dir)?;
+        }
 
-                for field in spec.fields_in_data_set(&data_set) {
-                    write!(output, "\t{}", field.fmt_raw_value(false))?;
-                }
+        Ok(())
+    }
 
-                write!(output, "\n")?;
+    /// Convert the log files in the directory of a host, uploading the output if configured.
+    async fn convert(&self, job: &Job) -> color_eyre::Result<()> {
+        let Job { host, dir, .. } = job;
+
+        // Converting blocks, so move it to its own thread to keep syncing the other hosts
+        let (sender, receiver) = oneshot::channel();
+        let dir = dir.clone();
+        let spec = job.spec.clone();
+        let convert_options = job.convert_options.clone();
+        thread::spawn(move || {
+            let _ = sender.send(convert(&dir, &spec, &convert_options));
+        });
+        let report = receiver.await??;
+        info!("{}: wrote {} files, {} up to date, {} skipped as empty", host.name, report.converted.len(), report.up_to_date, report.empty);
+        if let Some(influx_target) = &self.influx_target {
+            upload_to_influx(influx_target, &report.converted, &self.options).await?;
+        }
+        if let Some(s3_target) = &self.s3_target {
+            upload_to_s3(s3_target, job.output_dir(), &report.converted, &self.options).await?;
+        }
 
-                contains_data_lines = true;
-            }
+        Ok(())
+    }
 
-            if contains_data_lines {
-                std::fs::write(csv_filename, output_buffer)?;
-            } else {
-                debug!("    Skipping because CSV would be empty");
-            }
+    /// Write the CSV of the date range of a host to stdout, instead of converting into files.
+    async fn convert_to_stdout(&self, job: &Job) -> color_eyre::Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        let dir = job.dir.clone();
+        let spec = job.spec.clone();
+        let convert_options = job.convert_options.clone();
+        thread::spawn(move || {
+            let _ = sender.send(convert_dir_to_stream(&dir, Box::new(std::io::stdout()), &spec, &convert_options));
+        });
+        if !receiver.await?? {
+            warn!("{}: no data sets within the date range", job.host.name);
         }
-    }
 
-    Ok(())
+        Ok(())
+    }
 }
 
-fn setup_debugging() -> Result<()> {
+/// Install the error report handler and the tracing subscriber, logging one JSON object per line
+/// instead of human readable lines if `json` is set, and also to the `log_file` if given.
+fn setup_debugging(json: bool, log_file: Option<LogFile>, to_stderr: bool) -> color_eyre::Result<()> {
     if std::env::var("RUST_BACKTRACE").is_err() {
         std::env::set_var("RUST_BACKTRACE", "1")
     }
+    // Only capture backtraces for panics, errors are reported by their message
+    if std::env::var("RUST_LIB_BACKTRACE").is_err() {
+        std::env::set_var("RUST_LIB_BACKTRACE", "0")
+    }
     color_eyre::install()?;
 
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", "info")
     }
-    tracing_subscriber::fmt::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
+    let make_writer = move || -> Box<dyn Write> {
+        if to_stderr {
+            Box::new(std::io::stderr())
+        } else {
+            Box::new(std::io::stdout())
+        }
+    };
+    // Only one of each pair of layers is used, depending on the format
+    let (human_layer, json_layer) = if json {
+        (None, Some(fmt::layer().json().with_timer(ChronoUtc::rfc3339()).with_writer(make_writer)))
+    } else {
+        (Some(fmt::layer().with_writer(make_writer)), None)
+    };
+    let (human_file_layer, json_file_layer) = match log_file {
+        Some(log_file) if json => (None, Some(fmt::layer().json().with_timer(ChronoUtc::rfc3339()).with_writer(move || log_file.clone()))),
+        Some(log_file) => (Some(fmt::layer().with_ansi(false).with_writer(move || log_file.clone())), None),
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(human_layer)
+        .with(json_layer)
+        .with(human_file_layer)
+        .with(json_file_layer)
         .init();
 
     Ok(())
@@ -0,0 +1,33 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::{atomic::write_file_atomically, convert::scan_vbus_files, Result};
+
+/// Describe the `.vbus` files in a host directory in a `manifest.json` file next to them, e.g.
+/// so that another system can tell which days are missing.
+///
+/// It lists the `datecode`, `size` in bytes and `modified` time (RFC 3339 in UTC) of each file,
+/// sorted by datecode. Returns the name of the manifest.
+pub fn write_manifest(dir: &Path) -> Result<PathBuf> {
+    let files = scan_vbus_files(dir)?.into_iter().map(|vbus_file| {
+        let modified: DateTime<Utc> = vbus_file.modified.into();
+        json!({
+            "datecode": &vbus_file.filename [0..8],
+            "filename": vbus_file.filename,
+            "size": vbus_file.size,
+            "modified": modified.to_rfc3339(),
+        })
+    }).collect::<Vec<_>>();
+
+    let manifest = json!({
+        "generated": Utc::now().to_rfc3339(),
+        "files": files,
+    });
+
+    let filename = dir.join("manifest.json");
+    let contents = serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::from)?;
+    write_file_atomically(&filename, &contents)?;
+    Ok(filename)
+}
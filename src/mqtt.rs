@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use async_std::net::TcpStream;
+use futures::{AsyncReadExt, AsyncWriteExt};
+use tracing::debug;
+
+use crate::{client::with_timeout, host::bracketed, Error, Result};
+
+/// Options for publishing the converted data sets to an MQTT broker.
+#[derive(Debug, Clone)]
+pub struct MqttOptions {
+    /// The host name or IP address of the broker.
+    pub host: String,
+
+    /// The port of the broker.
+    pub port: u16,
+
+    /// The topic the topics of all fields start with, followed by `/<host>/<device>/<field>`.
+    pub base_topic: String,
+
+    /// The client identifier sent to the broker.
+    pub client_id: String,
+
+    /// Maximum time to wait for connecting or publishing.
+    pub timeout: Duration,
+}
+
+impl Default for MqttOptions {
+    fn default() -> MqttOptions {
+        MqttOptions {
+            host: "localhost".to_string(),
+            port: 1883,
+            base_topic: "vbus".to_string(),
+            client_id: "vbus-sync".to_string(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A connection to an MQTT broker that publishes messages with QoS 0 using MQTT 3.1.1.
+pub(crate) struct MqttClient {
+    stream: TcpStream,
+    timeout: Duration,
+}
+
+impl MqttClient {
+    pub(crate) async fn connect(options: &MqttOptions) -> Result<MqttClient> {
+        debug!("Connecting to MQTT broker {:?}...", options.host);
+
+        let addr = format!("{}:{}", bracketed(&options.host), options.port);
+        let stream = with_timeout(options.timeout, "connecting to MQTT broker", async { Ok(TcpStream::connect(addr).await?) }).await?;
+        let mut client = MqttClient {
+            stream,
+            timeout: options.timeout,
+        };
+
+        let mut packet = Vec::new();
+        put_string(&mut packet, "MQTT");
+        // Protocol level 4, clean session and no keep alive, since publishing never idles long
+        packet.extend_from_slice(&[4, 0x02, 0, 0]);
+        put_string(&mut packet, &options.client_id);
+        client.send(0x10, &packet).await?;
+
+        let mut connack = [0u8; 4];
+        with_timeout(client.timeout, "waiting for CONNACK", async { Ok(client.stream.read_exact(&mut connack).await?) }).await?;
+        match connack {
+            [0x20, 0x02, _, 0] => Ok(client),
+            [0x20, 0x02, _, code] => Err(Error::Other(format!("MQTT broker refused the connection with code {}", code))),
+            _ => Err(Error::Other("Unexpected response from MQTT broker".to_string())),
+        }
+    }
+
+    pub(crate) async fn publish(&mut self, topic: &str, payload: &[u8]) -> Result<()> {
+        let mut packet = Vec::with_capacity(topic.len() + payload.len() + 2);
+        put_string(&mut packet, topic);
+        packet.extend_from_slice(payload);
+        self.send(0x30, &packet).await
+    }
+
+    pub(crate) async fn disconnect(mut self) -> Result<()> {
+        self.send(0xE0, &[]).await
+    }
+
+    async fn send(&mut self, packet_type: u8, body: &[u8]) -> Result<()> {
+        let mut packet = Vec::with_capacity(body.len() + 5);
+        packet.push(packet_type);
+
+        let mut remaining_len = body.len();
+        loop {
+            let mut byte = (remaining_len % 128) as u8;
+            remaining_len /= 128;
+            if remaining_len > 0 {
+                byte |= 0x80;
+            }
+            packet.push(byte);
+            if remaining_len == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+
+        with_timeout(self.timeout, "sending to MQTT broker", async { Ok(self.stream.write_all(&packet).await?) }).await
+    }
+}
+
+/// Append a string prefixed with its length.
+fn put_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    packet.extend_from_slice(s.as_bytes());
+}
+
+/// Turn a name into a single topic level, replacing the characters MQTT reserves.
+pub(crate) fn topic_level(name: &str) -> String {
+    name.replace(['/', '+', '#'], "_")
+}
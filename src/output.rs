@@ -0,0 +1,252 @@
+use std::str::FromStr;
+
+use resol_vbus::{specification::DataSetPacketField, specification_file::Type, Data, DataSet, Language};
+
+use crate::{ColumnNames, Result};
+
+mod csv_writer;
+mod fields_writer;
+mod influx_writer;
+mod json_writer;
+mod mqtt_writer;
+mod parquet_writer;
+mod sqlite_writer;
+mod summary_writer;
+mod webhook_writer;
+
+pub(crate) use self::{csv_writer::{header_row, CsvWriter}, fields_writer::FieldsWriter, influx_writer::InfluxWriter, json_writer::JsonWriter, mqtt_writer::MqttWriter, parquet_writer::ParquetWriter, sqlite_writer::{SqliteDatabase, SqliteWriter}, summary_writer::SummaryWriter, webhook_writer::WebhookWriter};
+
+/// The file format the converted days are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One row per data set and one column per field.
+    Csv,
+
+    /// One JSON object per data set and line.
+    Json,
+
+    /// One row per data set in a single SQLite database instead of one file per day.
+    Sqlite,
+
+    /// One InfluxDB line protocol point per field and data set.
+    Influx,
+
+    /// One row per data set and one typed column per field in Apache Parquet.
+    Parquet,
+}
+
+impl OutputFormat {
+    /// All formats, e.g. to find the files written in any of them.
+    pub(crate) const ALL: [OutputFormat; 5] = [OutputFormat::Csv, OutputFormat::Json, OutputFormat::Sqlite, OutputFormat::Influx, OutputFormat::Parquet];
+
+    /// The extension of the files written in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "jsonl",
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::Influx => "lp",
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<OutputFormat, String> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" | "jsonl" | "ndjson" => Ok(OutputFormat::Json),
+            "sqlite" => Ok(OutputFormat::Sqlite),
+            "influx" | "lp" => Ok(OutputFormat::Influx),
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => Err(format!("Unknown output format {:?}, expected csv, json, sqlite, influx or parquet", s)),
+        }
+    }
+}
+
+/// Whether and how the VBus source address of the packet holding each field is shown, e.g. to
+/// tell which of several devices on a logger a value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceAddresses {
+    /// Do not show the source addresses.
+    #[default]
+    Hidden,
+
+    /// Prefix the header of each column with the source address, like `7E11: Temperatur Sensor 1`.
+    Headers,
+
+    /// Precede the columns of each packet in CSV files by a column holding its source address in
+    /// the rows containing that packet.
+    Columns,
+}
+
+impl FromStr for SourceAddresses {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<SourceAddresses, String> {
+        match s {
+            "hidden" | "none" => Ok(SourceAddresses::Hidden),
+            "headers" => Ok(SourceAddresses::Headers),
+            "columns" => Ok(SourceAddresses::Columns),
+            _ => Err(format!("Unknown source addresses {:?}, expected hidden, headers or columns", s)),
+        }
+    }
+}
+
+/// A field of a data set, holding its specification and value.
+pub(crate) type Field<'a> = DataSetPacketField<'a, DataSet>;
+
+/// Writes the data sets of a single day into an output file.
+///
+/// Only the selected fields are passed, in the same order for the header and each data set.
+pub(crate) trait DayWriter {
+    /// Write anything preceding the data sets, given the fields all data sets are expected to have.
+    fn write_header(&mut self, fields: &[Field<'_>]) -> Result<()>;
+
+    /// Write a data set, which has been merged into the expected topology.
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()>;
+
+    /// Move the output file into place.
+    fn commit(self: Box<Self>) -> Result<()>;
+}
+
+/// The default name of the first column, which holds the timestamp of each data set.
+fn default_timestamp_column_name(language: Language) -> &'static str {
+    match language {
+        Language::En => "Date",
+        Language::De => "Datum",
+        Language::Fr => "Date",
+    }
+}
+
+/// How the columns holding fields are named.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ColumnHeaders<'a> {
+    pub(crate) names: &'a ColumnNames,
+    pub(crate) timestamp: Option<&'a str>,
+    pub(crate) source_addresses: SourceAddresses,
+}
+
+impl<'a> ColumnHeaders<'a> {
+    /// The name of the first column, which holds the timestamp of each data set.
+    pub(crate) fn timestamp_column_name(&self, language: Language) -> &'a str {
+        self.timestamp.unwrap_or_else(|| default_timestamp_column_name(language))
+    }
+
+    /// The name of the column holding a field and the unit to append to it, which is empty for
+    /// display names.
+    pub(crate) fn name_and_unit<'f>(&self, field: &'f Field<'_>) -> (String, &'f str) {
+        let field_spec = field.field_spec();
+        let (name, unit_text) = match self.names.get(&field_spec.name) {
+            Some(display_name) => (display_name.to_string(), ""),
+            None => (field_spec.name.clone(), field_spec.unit_text.trim()),
+        };
+        match self.source_addresses {
+            SourceAddresses::Headers => (format!("{:04X}: {}", field.packet_spec().source_address, name), unit_text),
+            SourceAddresses::Hidden | SourceAddresses::Columns => (name, unit_text),
+        }
+    }
+
+    /// The name of the column holding a field, like `Temperatur Sensor 1 [°C]`, unless it has a
+    /// display name.
+    pub(crate) fn column_name(&self, field: &Field<'_>) -> String {
+        match self.name_and_unit(field) {
+            (name, "") => name,
+            (name, unit_text) => format!("{} [{}]", name, unit_text),
+        }
+    }
+
+    /// Whether the field at `index` is preceded by a column holding the source address of its
+    /// packet, which is the case for the first field of each packet if enabled.
+    pub(crate) fn has_address_column(&self, fields: &[Field<'_>], index: usize) -> bool {
+        self.source_addresses == SourceAddresses::Columns
+            && (index == 0 || fields [index - 1].data_index() != fields [index].data_index())
+    }
+}
+
+/// The name of the column holding the source address of the packet of a field, like
+/// `DeltaSol BS Plus: Quelladresse`.
+pub(crate) fn address_column_name(field: &Field<'_>, language: Language) -> String {
+    let label = match language {
+        Language::En => "Source address",
+        Language::De => "Quelladresse",
+        Language::Fr => "Adresse source",
+    };
+    format!("{}: {}", field.packet_spec().name, label)
+}
+
+/// The source address of the packet of a field, empty unless a data set contains that packet.
+pub(crate) fn address_value(field: &Field<'_>) -> String {
+    match field.data() {
+        Data::Packet(packet) if packet.frame_count > 0 => format!("{:04X}", packet.header.source_address),
+        _ => String::new(),
+    }
+}
+
+/// Writes the same data sets using several writers.
+pub(crate) struct TeeWriter<'a> {
+    writers: Vec<Box<dyn DayWriter + 'a>>,
+}
+
+impl<'a> TeeWriter<'a> {
+    pub(crate) fn new(writers: Vec<Box<dyn DayWriter + 'a>>) -> TeeWriter<'a> {
+        TeeWriter {
+            writers,
+        }
+    }
+}
+
+impl DayWriter for TeeWriter<'_> {
+    fn write_header(&mut self, fields: &[Field<'_>]) -> Result<()> {
+        for writer in &mut self.writers {
+            writer.write_header(fields)?;
+        }
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        for writer in &mut self.writers {
+            writer.write_data_set(data_set, fields)?;
+        }
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<()> {
+        for writer in self.writers {
+            writer.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// The value of a field, typed for formats that distinguish numbers from text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    /// The field has no value, e.g. because its packet was not part of the data set.
+    Null,
+
+    /// A number without decimal places.
+    Integer(i64),
+
+    /// A number with decimal places.
+    Real(f64),
+
+    /// Any other value, like a time or date, as it would appear in a CSV file.
+    Text(String),
+}
+
+impl FieldValue {
+    /// Numbers become integers or reals depending on their precision, other values are text as
+    /// they would appear in a CSV file.
+    pub(crate) fn of(field: &Field<'_>) -> FieldValue {
+        let field_spec = field.field_spec();
+        match (field_spec.typ, *field.raw_value_i64()) {
+            (_, None) => FieldValue::Null,
+            (Type::Number, Some(raw_value)) if field_spec.precision <= 0 => FieldValue::Integer(raw_value),
+            (Type::Number, Some(raw_value)) => FieldValue::Real(raw_value as f64 / 10f64.powi(field_spec.precision)),
+            (_, Some(_)) => FieldValue::Text(field.fmt_raw_value(false).to_string()),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::{io::Write, path::Path};
+
+use resol_vbus::{DataSet, Language};
+
+use crate::{atomic::OutputFile, format::CsvFormat, output::{address_column_name, address_value, ColumnHeaders, DayWriter, Field}, Result};
+
+/// Writes one row per data set, with a header row naming the fields.
+pub(crate) struct CsvWriter<'a> {
+    output: csv::Writer<OutputFile>,
+    format: CsvFormat,
+    headers: ColumnHeaders<'a>,
+    timestamp_format: String,
+    language: Language,
+    tz: chrono_tz::Tz,
+
+    /// Whether the header row was copied from an existing file that rows are appended to.
+    has_header: bool,
+}
+
+impl<'a> CsvWriter<'a> {
+    pub(crate) fn create(filename: &Path, gzip: bool, format: CsvFormat, headers: ColumnHeaders<'a>, timestamp_format: &str, language: Language, tz: chrono_tz::Tz) -> Result<CsvWriter<'a>> {
+        let output = create_csv_file(filename, gzip, format)?;
+        Ok(CsvWriter::new(output, format, headers, timestamp_format, language, tz, false))
+    }
+
+    /// Replace an uncompressed CSV file by one starting with its `contents`, including the
+    /// header row, to append further rows to.
+    pub(crate) fn append(filename: &Path, contents: &[u8], format: CsvFormat, headers: ColumnHeaders<'a>, timestamp_format: &str, language: Language, tz: chrono_tz::Tz) -> Result<CsvWriter<'a>> {
+        let mut output = OutputFile::create(filename, false)?;
+        output.write_all(contents)?;
+        Ok(CsvWriter::new(output, format, headers, timestamp_format, language, tz, true))
+    }
+
+    /// Write to a stream like stdout instead of a file.
+    pub(crate) fn to_stream(output: Box<dyn Write>, format: CsvFormat, headers: ColumnHeaders<'a>, timestamp_format: &str, language: Language, tz: chrono_tz::Tz) -> Result<CsvWriter<'a>> {
+        let mut output = OutputFile::Stream(output);
+        if format.bom() {
+            output.write_all("\u{feff}".as_bytes())?;
+        }
+        Ok(CsvWriter::new(output, format, headers, timestamp_format, language, tz, false))
+    }
+
+    fn new(output: OutputFile, format: CsvFormat, headers: ColumnHeaders<'a>, timestamp_format: &str, language: Language, tz: chrono_tz::Tz, has_header: bool) -> CsvWriter<'a> {
+        // Rows may have more cells than the header if the assumed topology turns out to be wrong,
+        // which is detected afterwards and the output discarded
+        let output = csv::WriterBuilder::new()
+            .delimiter(format.delimiter())
+            .flexible(true)
+            .from_writer(output);
+
+        CsvWriter {
+            output,
+            format,
+            headers,
+            timestamp_format: timestamp_format.to_string(),
+            language,
+            tz,
+            has_header,
+        }
+    }
+}
+
+/// Create a CSV file, starting with a byte order mark if the format asks for it.
+pub(super) fn create_csv_file(filename: &Path, gzip: bool, format: CsvFormat) -> Result<OutputFile> {
+    let mut output = OutputFile::create(filename, gzip)?;
+    if format.bom() {
+        output.write_all("\u{feff}".as_bytes())?;
+    }
+    Ok(output)
+}
+
+/// The cells of the header row, naming the timestamp column followed by those of the fields.
+pub(crate) fn header_row(fields: &[Field<'_>], headers: ColumnHeaders<'_>, language: Language) -> Vec<String> {
+    let mut row = vec![headers.timestamp_column_name(language).to_string()];
+    for (index, field) in fields.iter().enumerate() {
+        if headers.has_address_column(fields, index) {
+            row.push(address_column_name(field, language));
+        }
+        row.push(headers.column_name(field));
+    }
+    row
+}
+
+impl DayWriter for CsvWriter<'_> {
+    fn write_header(&mut self, fields: &[Field<'_>]) -> Result<()> {
+        if self.has_header {
+            return Ok(());
+        }
+
+        self.output.write_record(header_row(fields, self.headers, self.language))?;
+
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        let local_now = data_set.timestamp.with_timezone(&self.tz);
+
+        self.output.write_field(local_now.format(&self.timestamp_format).to_string())?;
+
+        for (index, field) in fields.iter().enumerate() {
+            if self.headers.has_address_column(fields, index) {
+                self.output.write_field(address_value(field))?;
+            }
+            self.output.write_field(self.format.format_field(field))?;
+        }
+
+        self.output.write_record(None::<&[u8]>)?;
+
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<()> {
+        self.output.into_inner().map_err(|err| err.into_error())?.commit()
+    }
+}
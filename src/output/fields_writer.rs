@@ -0,0 +1,62 @@
+use std::{io::Write, path::Path};
+
+use resol_vbus::DataSet;
+use serde_json::{json, Value};
+
+use crate::{atomic::OutputFile, output::{ColumnHeaders, DayWriter, Field}, Result};
+
+/// Describes the columns of an output file in a JSON array, for consumers that need more than
+/// the column names.
+///
+/// Each object holds the `index` of the column (the timestamp being column 0), its `column` name,
+/// the field `name` and `unit`, the `device` name and VBus addresses of the packet and the
+/// `packet_field_id` also used by the SQLite databases.
+pub(crate) struct FieldsWriter<'a> {
+    output: OutputFile,
+    headers: ColumnHeaders<'a>,
+    fields: Vec<Value>,
+}
+
+impl<'a> FieldsWriter<'a> {
+    pub(crate) fn create(filename: &Path, headers: ColumnHeaders<'a>) -> Result<FieldsWriter<'a>> {
+        Ok(FieldsWriter {
+            output: OutputFile::create(filename, false)?,
+            headers,
+            fields: Vec::new(),
+        })
+    }
+}
+
+impl DayWriter for FieldsWriter<'_> {
+    fn write_header(&mut self, fields: &[Field<'_>]) -> Result<()> {
+        // Columns holding source addresses are not described, but still counted
+        let mut column_index = 0;
+        self.fields = fields.iter().enumerate().map(|(index, field)| {
+            column_index += if self.headers.has_address_column(fields, index) { 2 } else { 1 };
+            let packet_spec = field.packet_spec();
+            json!({
+                "index": column_index,
+                "column": self.headers.column_name(field),
+                "name": field.field_spec().name,
+                "unit": field.field_spec().unit_text.trim(),
+                "device": packet_spec.name,
+                "channel": packet_spec.channel,
+                "source_address": format!("{:04X}", packet_spec.source_address),
+                "destination_address": format!("{:04X}", packet_spec.destination_address),
+                "packet_field_id": field.packet_field_id().packet_field_id_string(),
+            })
+        }).collect();
+
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, _data_set: &DataSet, _fields: &[Field<'_>]) -> Result<()> {
+        Ok(())
+    }
+
+    fn commit(mut self: Box<Self>) -> Result<()> {
+        serde_json::to_writer_pretty(&mut self.output, &self.fields).map_err(std::io::Error::from)?;
+        self.output.write_all(b"\n")?;
+        self.output.commit()
+    }
+}
@@ -0,0 +1,73 @@
+use std::{io::{BufWriter, Write}, path::Path};
+
+use resol_vbus::DataSet;
+
+use crate::{atomic::AtomicFile, output::{DayWriter, Field, FieldValue}, Result};
+
+/// The measurement all points are written to.
+const MEASUREMENT: &str = "vbus";
+
+/// Writes InfluxDB line protocol, one point per field and data set.
+///
+/// Each point is tagged with the `host` directory, the `device` (packet name), the `field` name
+/// and its `unit`, and stores the field's value in `value`. Fields without a value are skipped,
+/// since line protocol has no notion of null.
+pub(crate) struct InfluxWriter {
+    output: BufWriter<AtomicFile>,
+    host: String,
+}
+
+impl InfluxWriter {
+    pub(crate) fn create(filename: &Path, host: &str) -> Result<InfluxWriter> {
+        Ok(InfluxWriter {
+            output: BufWriter::new(AtomicFile::create(filename)?),
+            host: host.to_string(),
+        })
+    }
+}
+
+impl DayWriter for InfluxWriter {
+    fn write_header(&mut self, _fields: &[Field<'_>]) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        let timestamp = data_set.timestamp.timestamp_nanos_opt().ok_or("Timestamp out of range")?;
+
+        for field in fields {
+            let value = match FieldValue::of(field) {
+                FieldValue::Null => continue,
+                FieldValue::Integer(value) => format!("{}i", value),
+                FieldValue::Real(value) => value.to_string(),
+                FieldValue::Text(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+            };
+
+            let field_spec = field.field_spec();
+
+            write!(self.output, "{},host={},device={},field={}", MEASUREMENT, escape_tag(&self.host), escape_tag(&field.packet_spec().name), escape_tag(&field_spec.name))?;
+            let unit_text = field_spec.unit_text.trim();
+            if !unit_text.is_empty() {
+                write!(self.output, ",unit={}", escape_tag(unit_text))?;
+            }
+            writeln!(self.output, " value={} {}", value, timestamp)?;
+        }
+
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<()> {
+        self.output.into_inner().map_err(|err| err.into_error())?.commit()
+    }
+}
+
+/// Escape a tag value, which must not contain unescaped commas, equal signs or spaces.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,83 @@
+use std::{io::{BufWriter, Write}, path::Path};
+
+use resol_vbus::DataSet;
+use serde_json::{Map, Value};
+
+use crate::{atomic::OutputFile, output::{DayWriter, Field, FieldValue}, Result};
+
+/// Writes one JSON object per data set and line.
+///
+/// Each object holds the `timestamp` and maps the packet names to their field names and values
+/// in `values`, and to the units of those fields in `units`. Numbers are written as such, other
+/// values as they would appear in a CSV file.
+pub(crate) struct JsonWriter {
+    output: BufWriter<OutputFile>,
+    tz: chrono_tz::Tz,
+}
+
+impl JsonWriter {
+    pub(crate) fn create(filename: &Path, gzip: bool, tz: chrono_tz::Tz) -> Result<JsonWriter> {
+        Ok(JsonWriter {
+            output: BufWriter::new(OutputFile::create(filename, gzip)?),
+            tz,
+        })
+    }
+}
+
+impl DayWriter for JsonWriter {
+    fn write_header(&mut self, _fields: &[Field<'_>]) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        let record = json_record(data_set, fields, self.tz);
+
+        serde_json::to_writer(&mut self.output, &record).map_err(std::io::Error::from)?;
+        writeln!(self.output)?;
+
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<()> {
+        self.output.into_inner().map_err(|err| err.into_error())?.commit()
+    }
+}
+
+/// The JSON object of a data set, holding its `timestamp` and the `values` and `units` of its
+/// fields by packet name.
+pub(super) fn json_record(data_set: &DataSet, fields: &[Field<'_>], tz: chrono_tz::Tz) -> Map<String, Value> {
+    let mut values = Map::new();
+    let mut units = Map::new();
+
+    for field in fields {
+        let packet_name = &field.packet_spec().name;
+        let field_spec = field.field_spec();
+
+        let value = match FieldValue::of(field) {
+            FieldValue::Null => Value::Null,
+            FieldValue::Integer(value) => Value::from(value),
+            FieldValue::Real(value) => Value::from(value),
+            FieldValue::Text(value) => Value::from(value),
+        };
+
+        insert_nested(&mut values, packet_name, &field_spec.name, value);
+
+        let unit_text = field_spec.unit_text.trim();
+        if !unit_text.is_empty() {
+            insert_nested(&mut units, packet_name, &field_spec.name, Value::from(unit_text));
+        }
+    }
+
+    let mut record = Map::new();
+    record.insert("timestamp".to_string(), Value::from(data_set.timestamp.with_timezone(&tz).to_rfc3339()));
+    record.insert("values".to_string(), Value::Object(values));
+    record.insert("units".to_string(), Value::Object(units));
+    record
+}
+
+fn insert_nested(map: &mut Map<String, Value>, outer_key: &str, inner_key: &str, value: Value) {
+    let inner = map.entry(outer_key).or_insert_with(|| Value::Object(Map::new()));
+    if let Value::Object(inner) = inner {
+        inner.insert(inner_key.to_string(), value);
+    }
+}
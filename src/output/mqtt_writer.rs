@@ -0,0 +1,57 @@
+use std::sync::Mutex;
+
+use resol_vbus::DataSet;
+
+use crate::{format::CsvFormat, mqtt::{topic_level, MqttClient}, output::{DayWriter, Field}, Result};
+
+/// Collects a message per field and data set of a single day and publishes them on commit.
+///
+/// The topic of each message is `<base topic>/<host>/<device>/<field>` and its payload is the
+/// value as it would appear in a CSV file. Fields without a value are skipped.
+pub(crate) struct MqttWriter<'a> {
+    client: &'a Mutex<MqttClient>,
+    topic_prefix: String,
+    format: CsvFormat,
+    messages: Vec<(String, String)>,
+}
+
+impl<'a> MqttWriter<'a> {
+    pub(crate) fn new(client: &'a Mutex<MqttClient>, base_topic: &str, host: &str, format: CsvFormat) -> MqttWriter<'a> {
+        MqttWriter {
+            client,
+            topic_prefix: format!("{}/{}", base_topic, topic_level(host)),
+            format,
+            messages: Vec::new(),
+        }
+    }
+}
+
+impl DayWriter for MqttWriter<'_> {
+    fn write_header(&mut self, _fields: &[Field<'_>]) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, _data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        for field in fields {
+            if field.raw_value_i64().is_none() {
+                continue;
+            }
+
+            let field_spec = field.field_spec();
+            let topic = format!("{}/{}/{}", self.topic_prefix, topic_level(&field.packet_spec().name), topic_level(&field_spec.name));
+            let payload = self.format.format_field(field);
+            self.messages.push((topic, payload));
+        }
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<()> {
+        let mut client = self.client.lock().unwrap();
+        async_std::task::block_on(async {
+            for (topic, payload) in &self.messages {
+                client.publish(topic, payload.as_bytes()).await?;
+            }
+            Ok(())
+        })
+    }
+}
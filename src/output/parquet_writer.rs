@@ -0,0 +1,135 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use parquet::{
+    basic::{Compression, LogicalType, Repetition, TimeUnit, Type as PhysicalType},
+    data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    format::MilliSeconds,
+    schema::types::Type,
+};
+use resol_vbus::{specification_file::Type as FieldType, DataSet, Language};
+
+use crate::{atomic::AtomicFile, output::{ColumnHeaders, DayWriter, Field, FieldValue}, Result};
+
+/// A column of the Parquet file besides the timestamp.
+struct Column {
+    name: String,
+    is_number: bool,
+}
+
+/// Collects the data sets of a single day and writes them as a single row group on commit.
+///
+/// The first column holds the timestamp in milliseconds since the Unix epoch, followed by a column
+/// per field named like in the CSV files. Names occurring more than once get `.1`, `.2`, etc.
+/// appended, just like pandas does when reading those CSV files. Numbers are stored as doubles,
+/// other values as strings like they would appear in a CSV file.
+pub(crate) struct ParquetWriter<'a> {
+    filename: Box<Path>,
+    language: Language,
+    headers: ColumnHeaders<'a>,
+    columns: Vec<Column>,
+    timestamps: Vec<i64>,
+    rows: Vec<Vec<FieldValue>>,
+}
+
+impl<'a> ParquetWriter<'a> {
+    pub(crate) fn create(filename: &Path, language: Language, headers: ColumnHeaders<'a>) -> Result<ParquetWriter<'a>> {
+        Ok(ParquetWriter {
+            filename: filename.into(),
+            language,
+            headers,
+            columns: Vec::new(),
+            timestamps: Vec::new(),
+            rows: Vec::new(),
+        })
+    }
+
+    fn schema(&self) -> Result<Arc<Type>> {
+        let timestamp_unit = TimeUnit::MILLIS(MilliSeconds {});
+        let mut fields = vec![Arc::new(
+            Type::primitive_type_builder(self.headers.timestamp_column_name(self.language), PhysicalType::INT64)
+                .with_repetition(Repetition::REQUIRED)
+                .with_logical_type(Some(LogicalType::Timestamp { is_adjusted_to_u_t_c: true, unit: timestamp_unit }))
+                .build()?,
+        )];
+
+        for column in &self.columns {
+            let field = if column.is_number {
+                Type::primitive_type_builder(&column.name, PhysicalType::DOUBLE)
+            } else {
+                Type::primitive_type_builder(&column.name, PhysicalType::BYTE_ARRAY).with_logical_type(Some(LogicalType::String))
+            };
+            fields.push(Arc::new(field.with_repetition(Repetition::OPTIONAL).build()?));
+        }
+
+        Ok(Arc::new(Type::group_type_builder("schema").with_fields(fields).build()?))
+    }
+}
+
+impl DayWriter for ParquetWriter<'_> {
+    fn write_header(&mut self, fields: &[Field<'_>]) -> Result<()> {
+        let mut name_counts = HashMap::new();
+        for field in fields {
+            let name = self.headers.column_name(field);
+            let count = name_counts.entry(name.clone()).or_insert(0);
+            let name = if *count > 0 { format!("{}.{}", name, count) } else { name };
+            *count += 1;
+
+            self.columns.push(Column {
+                name,
+                is_number: field.field_spec().typ == FieldType::Number,
+            });
+        }
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        self.timestamps.push(data_set.timestamp.timestamp_millis());
+        self.rows.push(fields.iter().map(FieldValue::of).collect());
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<()> {
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = SerializedFileWriter::new(AtomicFile::create(&self.filename)?, self.schema()?, Arc::new(props))?;
+
+        let mut row_group = writer.next_row_group()?;
+
+        if let Some(mut column_writer) = row_group.next_column()? {
+            column_writer.typed::<Int64Type>().write_batch(&self.timestamps, None, None)?;
+            column_writer.close()?;
+        }
+
+        for (index, column) in self.columns.iter().enumerate() {
+            let Some(mut column_writer) = row_group.next_column()? else {
+                break;
+            };
+
+            let values = self.rows.iter().map(|row| row.get(index).unwrap_or(&FieldValue::Null));
+            let def_levels = values.clone().map(|value| if let FieldValue::Null = value { 0 } else { 1 }).collect::<Vec<i16>>();
+
+            if column.is_number {
+                let values = values.filter_map(|value| match value {
+                    FieldValue::Integer(value) => Some(*value as f64),
+                    FieldValue::Real(value) => Some(*value),
+                    FieldValue::Null | FieldValue::Text(_) => None,
+                }).collect::<Vec<_>>();
+                column_writer.typed::<DoubleType>().write_batch(&values, Some(&def_levels), None)?;
+            } else {
+                let values = values.filter_map(|value| match value {
+                    FieldValue::Text(value) => Some(ByteArray::from(value.as_str())),
+                    _ => None,
+                }).collect::<Vec<_>>();
+                column_writer.typed::<ByteArrayType>().write_batch(&values, Some(&def_levels), None)?;
+            }
+
+            column_writer.close()?;
+        }
+
+        row_group.close()?;
+
+        writer.into_inner()?.commit()
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::HashSet, path::Path, sync::Mutex};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use resol_vbus::DataSet;
+use rusqlite::{params, types::Value, params_from_iter, Connection, OpenFlags, OptionalExtension};
+
+use crate::{output::{DayWriter, Field, FieldValue}, Result};
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A SQLite database holding the data sets of all days.
+///
+/// The `data_sets` table has a `timestamp` column (UTC, `YYYY-MM-DD HH:MM:SS`) and one column per
+/// field, named after its packet field ID. The `fields` table maps those column names to the
+/// packet names, field names and units.
+pub(crate) struct SqliteDatabase {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteDatabase {
+    /// Open the database, creating it and its tables if needed.
+    pub(crate) fn open(filename: &Path) -> Result<SqliteDatabase> {
+        let conn = Connection::open(filename)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS data_sets (timestamp TEXT PRIMARY KEY NOT NULL);
+             CREATE TABLE IF NOT EXISTS fields (column_name TEXT PRIMARY KEY NOT NULL, packet_name TEXT NOT NULL, field_name TEXT NOT NULL, unit TEXT NOT NULL);",
+        )?;
+        Ok(SqliteDatabase {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Open an existing database without creating or changing it, returning `None` if it does
+    /// not exist yet.
+    pub(crate) fn open_read_only(filename: &Path) -> Result<Option<SqliteDatabase>> {
+        if !filename.exists() {
+            return Ok(None);
+        }
+        let conn = Connection::open_with_flags(filename, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Some(SqliteDatabase {
+            conn: Mutex::new(conn),
+        }))
+    }
+
+    /// The timestamp of the latest data set stored so far.
+    pub(crate) fn latest_timestamp(&self) -> Result<Option<DateTime<Utc>>> {
+        let conn = self.conn.lock().unwrap();
+        let latest: Option<String> = conn.query_row("SELECT MAX(timestamp) FROM data_sets", [], |row| row.get(0)).optional()?.flatten();
+        match latest {
+            Some(latest) => Ok(Some(Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(&latest, TIMESTAMP_FORMAT)?))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A field of the `data_sets` table.
+struct Column {
+    name: String,
+    packet_name: String,
+    field_name: String,
+    unit: String,
+}
+
+/// Collects the data sets of a single day and upserts them into a `SqliteDatabase` on commit.
+pub(crate) struct SqliteWriter<'a> {
+    database: &'a SqliteDatabase,
+    columns: Vec<Column>,
+    rows: Vec<(String, Vec<(String, FieldValue)>)>,
+}
+
+impl<'a> SqliteWriter<'a> {
+    pub(crate) fn new(database: &'a SqliteDatabase) -> SqliteWriter<'a> {
+        SqliteWriter {
+            database,
+            columns: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+}
+
+impl DayWriter for SqliteWriter<'_> {
+    fn write_header(&mut self, fields: &[Field<'_>]) -> Result<()> {
+        for field in fields {
+            let field_spec = field.field_spec();
+            self.columns.push(Column {
+                name: field.packet_field_id().packet_field_id_string(),
+                packet_name: field.packet_spec().name.clone(),
+                field_name: field_spec.name.clone(),
+                unit: field_spec.unit_text.trim().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        let timestamp = data_set.timestamp.format(TIMESTAMP_FORMAT).to_string();
+        let values = fields.iter()
+            .map(|field| (field.packet_field_id().packet_field_id_string(), FieldValue::of(field)))
+            .collect();
+        self.rows.push((timestamp, values));
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<()> {
+        let mut conn = self.database.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let existing_columns = {
+            let mut stmt = tx.prepare("SELECT name FROM pragma_table_info('data_sets')")?;
+            let names = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<rusqlite::Result<HashSet<_>>>()?;
+            names
+        };
+
+        for column in &self.columns {
+            if !existing_columns.contains(&column.name) {
+                tx.execute(&format!("ALTER TABLE data_sets ADD COLUMN {}", quote_identifier(&column.name)), [])?;
+            }
+            tx.execute(
+                "INSERT OR REPLACE INTO fields (column_name, packet_name, field_name, unit) VALUES (?1, ?2, ?3, ?4)",
+                params![column.name, column.packet_name, column.field_name, column.unit],
+            )?;
+        }
+
+        for (timestamp, values) in self.rows {
+            let column_names = values.iter().map(|(name, _)| quote_identifier(name)).collect::<Vec<_>>();
+            let placeholders = (0..=values.len()).map(|index| format!("?{}", index + 1)).collect::<Vec<_>>();
+            let updates = column_names.iter().map(|name| format!("{0} = excluded.{0}", name)).collect::<Vec<_>>();
+
+            let mut sql = format!("INSERT INTO data_sets (timestamp{}) VALUES ({})", column_names.iter().map(|name| format!(", {}", name)).collect::<String>(), placeholders.join(", "));
+            if updates.is_empty() {
+                sql.push_str(" ON CONFLICT (timestamp) DO NOTHING");
+            } else {
+                sql.push_str(&format!(" ON CONFLICT (timestamp) DO UPDATE SET {}", updates.join(", ")));
+            }
+
+            let params = std::iter::once(Value::Text(timestamp)).chain(values.into_iter().map(|(_, value)| match value {
+                FieldValue::Null => Value::Null,
+                FieldValue::Integer(value) => Value::Integer(value),
+                FieldValue::Real(value) => Value::Real(value),
+                FieldValue::Text(value) => Value::Text(value),
+            }));
+
+            tx.prepare_cached(&sql)?.execute(params_from_iter(params))?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
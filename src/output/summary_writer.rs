@@ -0,0 +1,136 @@
+use std::path::Path;
+
+use chrono::NaiveDate;
+use resol_vbus::{specification_file::Type, DataSet, Language};
+
+use crate::{atomic::OutputFile, format::CsvFormat, output::{csv_writer::create_csv_file, ColumnHeaders, DayWriter, Field}, Result};
+
+/// The minimum, maximum and sum of the raw values of a number.
+#[derive(Debug, Clone, Copy)]
+struct Stats {
+    min: i64,
+    max: i64,
+    sum: i64,
+    count: i64,
+}
+
+/// A number field and its statistics.
+struct Column {
+    name: String,
+    unit_text: String,
+    precision: i32,
+    stats: Option<Stats>,
+}
+
+/// Writes a single row with the minimum, maximum and mean of each number of the day.
+pub(crate) struct SummaryWriter<'a> {
+    output: csv::Writer<OutputFile>,
+    format: CsvFormat,
+    headers: ColumnHeaders<'a>,
+    language: Language,
+    tz: chrono_tz::Tz,
+    date: Option<NaiveDate>,
+
+    /// The numbers among the fields, `None` for other fields to keep the indices aligned.
+    columns: Vec<Option<Column>>,
+}
+
+impl<'a> SummaryWriter<'a> {
+    pub(crate) fn create(filename: &Path, format: CsvFormat, headers: ColumnHeaders<'a>, language: Language, tz: chrono_tz::Tz) -> Result<SummaryWriter<'a>> {
+        let output = csv::WriterBuilder::new()
+            .delimiter(format.delimiter())
+            .from_writer(create_csv_file(filename, false, format)?);
+
+        Ok(SummaryWriter {
+            output,
+            format,
+            headers,
+            language,
+            tz,
+            date: None,
+            columns: Vec::new(),
+        })
+    }
+}
+
+impl DayWriter for SummaryWriter<'_> {
+    fn write_header(&mut self, fields: &[Field<'_>]) -> Result<()> {
+        self.columns = fields.iter().map(|field| {
+            let field_spec = field.field_spec();
+            let (name, unit_text) = self.headers.name_and_unit(field);
+            (field_spec.typ == Type::Number).then(|| Column {
+                name,
+                unit_text: unit_text.to_string(),
+                precision: field_spec.precision,
+                stats: None,
+            })
+        }).collect();
+
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        if self.date.is_none() {
+            self.date = Some(data_set.timestamp.with_timezone(&self.tz).date_naive());
+        }
+
+        for (column, field) in self.columns.iter_mut().zip(fields) {
+            if let (Some(column), Some(raw_value)) = (column, *field.raw_value_i64()) {
+                column.stats = Some(match column.stats {
+                    Some(stats) => Stats {
+                        min: stats.min.min(raw_value),
+                        max: stats.max.max(raw_value),
+                        sum: stats.sum + raw_value,
+                        count: stats.count + 1,
+                    },
+                    None => Stats {
+                        min: raw_value,
+                        max: raw_value,
+                        sum: raw_value,
+                        count: 1,
+                    },
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn commit(mut self: Box<Self>) -> Result<()> {
+        let columns = self.columns.iter().flatten().collect::<Vec<_>>();
+
+        self.output.write_field(self.headers.timestamp_column_name(self.language))?;
+        for column in &columns {
+            for statistic in ["min", "max", "mean"] {
+                if column.unit_text.is_empty() {
+                    self.output.write_field(format!("{} {}", column.name, statistic))?;
+                } else {
+                    self.output.write_field(format!("{} {} [{}]", column.name, statistic, column.unit_text))?;
+                }
+            }
+        }
+        self.output.write_record(None::<&[u8]>)?;
+
+        let date = self.date.map(|date| date.format("%d.%m.%Y").to_string()).unwrap_or_default();
+        self.output.write_field(date)?;
+        for column in &columns {
+            let scale = 10f64.powi(column.precision);
+            match column.stats {
+                Some(stats) => {
+                    let mean = stats.sum as f64 / stats.count as f64;
+                    for raw_value in [stats.min as f64, stats.max as f64, mean] {
+                        self.output.write_field(self.format.format_number(raw_value / scale, column.precision))?;
+                    }
+                }
+                None => {
+                    for _ in 0..3 {
+                        self.output.write_field("")?;
+                    }
+                }
+            }
+        }
+        self.output.write_record(None::<&[u8]>)?;
+
+        self.output.into_inner().map_err(|err| err.into_error())?.commit()
+    }
+}
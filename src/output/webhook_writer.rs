@@ -0,0 +1,68 @@
+use resol_vbus::DataSet;
+use serde_json::{json, Map, Value};
+
+use crate::{client::Client, output::{json_writer::json_record, DayWriter, Field}, webhook::{post_json, WebhookBatch}, Result};
+
+/// Collects the data sets of a single day as JSON objects like `JsonWriter` and POSTs them to a
+/// webhook on commit, either all at once or one request per data set.
+///
+/// Each request body holds the `host` directory name, the `day` and the `records` sent.
+pub(crate) struct WebhookWriter<'a> {
+    client: &'a Client<'a>,
+    path: &'a str,
+    headers: &'a [(String, String)],
+    batch: WebhookBatch,
+    host: String,
+    day: String,
+    tz: chrono_tz::Tz,
+    records: Vec<Map<String, Value>>,
+}
+
+impl<'a> WebhookWriter<'a> {
+    pub(crate) fn new(client: &'a Client<'a>, path: &'a str, headers: &'a [(String, String)], batch: WebhookBatch, host: &str, day: &str, tz: chrono_tz::Tz) -> WebhookWriter<'a> {
+        WebhookWriter {
+            client,
+            path,
+            headers,
+            batch,
+            host: host.to_string(),
+            day: day.to_string(),
+            tz,
+            records: Vec::new(),
+        }
+    }
+
+    fn body(&self, records: &[Map<String, Value>]) -> Result<Vec<u8>> {
+        let body = json!({
+            "host": self.host,
+            "day": self.day,
+            "records": records,
+        });
+        Ok(serde_json::to_vec(&body).map_err(std::io::Error::from)?)
+    }
+}
+
+impl DayWriter for WebhookWriter<'_> {
+    fn write_header(&mut self, _fields: &[Field<'_>]) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_data_set(&mut self, data_set: &DataSet, fields: &[Field<'_>]) -> Result<()> {
+        self.records.push(json_record(data_set, fields, self.tz));
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> Result<()> {
+        let batch_size = match self.batch {
+            WebhookBatch::Day => self.records.len().max(1),
+            WebhookBatch::DataSet => 1,
+        };
+        async_std::task::block_on(async {
+            for records in self.records.chunks(batch_size) {
+                let body = self.body(records)?;
+                post_json(self.client, self.path, self.headers, &body).await?;
+            }
+            Ok(())
+        })
+    }
+}
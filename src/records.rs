@@ -0,0 +1,87 @@
+use std::ops::{Bound, RangeBounds};
+
+use chrono::{DateTime, Duration, Utc};
+use resol_vbus::RecordingReader;
+use tracing::warn;
+
+use crate::{valid_records::copy_valid_records, output::FieldValue, sync::fetch_log_file, Host, Result, SpecSource, SyncOptions};
+
+/// A data set decoded in memory by `convert_to_records`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// When the data set was recorded.
+    pub timestamp: DateTime<Utc>,
+
+    /// The fields of the packets contained in the data set.
+    pub fields: Vec<RecordField>,
+}
+
+/// A field of a `Record` and its value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordField {
+    /// The ID of the field, like `00_0010_4221_10_0100_000_2_0`, also used in SQLite databases.
+    pub id: String,
+
+    /// The name of the field in the language of the specification, like `Temperature sensor 1`.
+    pub name: String,
+
+    /// The unit of the field, like `°C`, empty if it has none.
+    pub unit: String,
+
+    /// The value of the field.
+    pub value: FieldValue,
+}
+
+/// Decode the data sets within `range` from the contents of `.vbus` files, without writing any
+/// files, e.g. to store them elsewhere.
+///
+/// Unlike the rows of the output files, each record only holds the fields of the packets
+/// received for its data set. Corrupt records are skipped with a warning.
+pub fn convert_to_records(bytes: &[u8], spec: &SpecSource, range: impl RangeBounds<DateTime<Utc>>) -> Result<Vec<Record>> {
+    let spec = spec.load()?;
+
+    let mut recording = Vec::new();
+    copy_valid_records(bytes, &mut recording, |offset, reason| {
+        warn!("Skipping corrupt record at offset {}: {}", offset, reason);
+        Ok(())
+    })?;
+
+    // VBus timestamps have millisecond precision
+    let min_timestamp = match range.start_bound() {
+        Bound::Included(start) => Some(*start),
+        Bound::Excluded(start) => Some(*start + Duration::milliseconds(1)),
+        Bound::Unbounded => None,
+    };
+    let max_timestamp = match range.end_bound() {
+        Bound::Included(end) => Some(*end + Duration::milliseconds(1)),
+        Bound::Excluded(end) => Some(*end),
+        Bound::Unbounded => None,
+    };
+
+    let mut rr = RecordingReader::new(&recording [..]);
+    rr.set_min_max_timestamps(min_timestamp, max_timestamp);
+
+    let mut records = Vec::new();
+    while let Some(data_set) = rr.read_data_set()? {
+        let fields = spec.fields_in_data_set(&data_set).map(|field| RecordField {
+            id: field.packet_field_id().packet_field_id_string(),
+            name: field.field_spec().name.clone(),
+            unit: field.field_spec().unit_text.trim().to_string(),
+            value: FieldValue::of(&field),
+        }).collect();
+
+        records.push(Record {
+            timestamp: data_set.timestamp,
+            fields,
+        });
+    }
+
+    Ok(records)
+}
+
+/// Download the log file of a UTC day from a host and decode its data sets within `range`
+/// using `convert_to_records`, without writing any files.
+pub async fn fetch_records(host: &Host, datecode: &str, spec: &SpecSource, range: impl RangeBounds<DateTime<Utc>>, options: &SyncOptions) -> Result<Vec<Record>> {
+    let bytes = fetch_log_file(host, datecode, options).await?;
+    convert_to_records(&bytes, spec, range)
+}
@@ -0,0 +1,135 @@
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use chrono::{TimeZone, Utc};
+use resol_vbus::{specification_file::{PacketTemplateFieldPart, Type}, Data, DataSet, PacketId, Specification};
+
+/// How the data sets within an interval are combined when resampling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleMode {
+    /// Keep the first data set of each interval.
+    #[default]
+    First,
+
+    /// Average the numbers of all data sets within each interval, taking the last value of other
+    /// fields like times.
+    Mean,
+}
+
+impl FromStr for ResampleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<ResampleMode, String> {
+        match s {
+            "first" => Ok(ResampleMode::First),
+            "mean" | "average" => Ok(ResampleMode::Mean),
+            _ => Err(format!("Unknown resample mode {:?}, expected first or mean", s)),
+        }
+    }
+}
+
+/// Reduces the data sets to at most one per interval, timestamped with the start of the interval.
+///
+/// Intervals start at multiples of `interval` since the Unix epoch, so intervals dividing a day
+/// start at midnight UTC.
+#[derive(Debug, Clone, Copy)]
+pub struct Resample {
+    /// The length of each interval.
+    pub interval: Duration,
+
+    /// How the data sets within an interval are combined.
+    pub mode: ResampleMode,
+}
+
+/// Combines the data sets of a day interval by interval.
+pub(crate) struct Resampler<'a> {
+    spec: &'a Specification,
+    interval_ms: i64,
+    mode: ResampleMode,
+
+    /// The index of the current interval and the data set to write for it so far.
+    current: Option<(i64, DataSet)>,
+
+    /// The sum and count of the raw values of each number within the current interval.
+    sums: HashMap<(PacketId, usize), (i64, i64)>,
+}
+
+impl<'a> Resampler<'a> {
+    pub(crate) fn new(spec: &'a Specification, resample: &Resample) -> Resampler<'a> {
+        Resampler {
+            spec,
+            interval_ms: (resample.interval.as_millis() as i64).max(1),
+            mode: resample.mode,
+            current: None,
+            sums: HashMap::new(),
+        }
+    }
+
+    /// Add the next data set, returning the combined data set of the previous interval once
+    /// this one starts a new interval.
+    pub(crate) fn push(&mut self, data_set: DataSet) -> Option<DataSet> {
+        let index = data_set.timestamp.timestamp_millis().div_euclid(self.interval_ms);
+
+        let finished = match &self.current {
+            Some((current_index, _)) if *current_index != index => self.finish(),
+            _ => None,
+        };
+
+        if self.mode == ResampleMode::Mean {
+            for field in self.spec.fields_in_data_set(&data_set) {
+                if let (Type::Number, Some(raw_value)) = (field.field_spec().typ, *field.raw_value_i64()) {
+                    let sum = self.sums.entry((field.packet_id(), field.field_index())).or_default();
+                    sum.0 += raw_value;
+                    sum.1 += 1;
+                }
+            }
+        }
+
+        match (&mut self.current, self.mode) {
+            (Some(_), ResampleMode::First) => {}
+            (current, _) => *current = Some((index, data_set)),
+        }
+
+        finished
+    }
+
+    /// Return the combined data set of the current interval, if any.
+    pub(crate) fn finish(&mut self) -> Option<DataSet> {
+        let (index, mut data_set) = self.current.take()?;
+
+        data_set.timestamp = Utc.timestamp_millis_opt(index * self.interval_ms).unwrap();
+
+        if self.mode == ResampleMode::Mean {
+            let means = self.spec.fields_in_data_set(&data_set)
+                .filter_map(|field| {
+                    let (sum, count) = self.sums.get(&(field.packet_id(), field.field_index()))?;
+                    let mean = (*sum as f64 / *count as f64).round() as i64;
+                    Some((field.data_index(), field.field_spec().parts.clone(), mean))
+                })
+                .collect::<Vec<_>>();
+
+            let mut data = data_set.as_data_slice().to_vec();
+            for (data_index, parts, mean) in means {
+                if let Data::Packet(packet) = &mut data [data_index] {
+                    let len = packet.frame_count as usize * 4;
+                    set_raw_value(&parts, &mut packet.frame_data [..len], mean);
+                }
+            }
+            data_set = DataSet::from_data(data_set.timestamp, data);
+
+            self.sums.clear();
+        }
+
+        Some(data_set)
+    }
+}
+
+/// Store a raw value into the frame data of a packet, the inverse of
+/// `PacketFieldSpec::raw_value_i64`.
+fn set_raw_value(parts: &[PacketTemplateFieldPart], buf: &mut [u8], raw_value: i64) {
+    for part in parts {
+        if let Some(byte) = buf.get_mut(part.offset as usize) {
+            let part_value = (raw_value.div_euclid(part.factor) << part.bit_pos) as u8;
+            *byte = (*byte & !part.mask) | (part_value & part.mask);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{fs::read_dir, path::{Path, PathBuf}};
+
+use chrono::{NaiveDate, Utc};
+use tracing::info;
+
+use crate::{convert::{datecode_prefix, parse_datecode, LIVE_FILE_SUFFIX, SIDECAR_FILE_SUFFIXES}, FilenamePattern, OutputFormat, Result};
+
+/// What follows the datecode in the names of the log files, their `.meta` and `.sha256` files and
+/// the live recordings.
+const LOG_FILE_SUFFIXES: &[&str] = &[".vbus", ".vbus.meta", ".vbus.sha256", LIVE_FILE_SUFFIX];
+
+/// What follows the name of the output file of a day in the names of the files written for it, in
+/// any of the formats with one file per day.
+fn output_file_suffixes() -> Vec<String> {
+    let mut suffixes = Vec::new();
+    for format in OutputFormat::ALL {
+        match format {
+            OutputFormat::Sqlite => {}
+            OutputFormat::Csv | OutputFormat::Json => {
+                suffixes.push(format!(".{}", format.extension()));
+                suffixes.push(format!(".{}.gz", format.extension()));
+            }
+            OutputFormat::Influx | OutputFormat::Parquet => suffixes.push(format!(".{}", format.extension())),
+        }
+    }
+    suffixes.extend(SIDECAR_FILE_SUFFIXES.map(str::to_string));
+    suffixes
+}
+
+/// Remove the files of the days before `cutoff` from a host directory, the log files and, if
+/// `output_files` gives the pattern they are named after, the output files as well.
+///
+/// The output files are searched for in the subdirectories the pattern creates as well, in any
+/// of the formats, together with their summary, fields and gaps files. Files of the cutoff day or
+/// later, and files not named after a day, are never removed. Returns the files removed, or that
+/// would be removed during a dry run.
+pub fn remove_old_files(dir: &Path, cutoff: NaiveDate, output_files: Option<&FilenamePattern>, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+
+    // Nothing was downloaded into a new directory during a dry run
+    if !dir.is_dir() {
+        return Ok(removed);
+    }
+
+    let host = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let output_file_suffixes = output_file_suffixes();
+
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current_dir) = dirs.pop() {
+        for entry in read_dir(&current_dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() && output_files.is_some() {
+                dirs.push(entry.path());
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+
+            let filename = entry.path();
+            let day = if current_dir == dir {
+                log_file_day(&entry.file_name().to_string_lossy())
+            } else {
+                None
+            };
+            let day = day.or_else(|| {
+                // Output files are named relative to the host directory, using `/` for subdirectories
+                let rel_filename = filename.strip_prefix(dir).ok()?.components().map(|component| component.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+                output_file_suffixes.iter()
+                    .filter_map(|suffix| rel_filename.strip_suffix(suffix.as_str()))
+                    .find_map(|name| output_files?.parse_date(name, &host))
+            });
+            if day.is_none_or(|day| day >= cutoff) {
+                continue;
+            }
+
+            if dry_run {
+                info!("Would remove {:?}", filename);
+            } else {
+                std::fs::remove_file(&filename)?;
+            }
+            removed.push(filename);
+        }
+    }
+
+    removed.sort();
+
+    Ok(removed)
+}
+
+/// The UTC day of a log file named `<DATECODE>` followed by one of the `LOG_FILE_SUFFIXES`.
+fn log_file_day(filename: &str) -> Option<NaiveDate> {
+    let datecode = datecode_prefix(filename)?;
+    if !LOG_FILE_SUFFIXES.contains(&&filename [datecode.len()..]) {
+        return None;
+    }
+    // Skip datecodes that do not name a day, like `convert` does
+    Some(parse_datecode(datecode, &Utc).ok()?.date_naive())
+}
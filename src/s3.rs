@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac, NewMac};
+use http_types::Method;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{client::{read_body_with_progress, Client}, Error, Host, Result, SyncOptions};
+
+/// A bucket of an S3-compatible object storage like MinIO, addressed path-style.
+#[derive(Debug)]
+pub struct S3Target {
+    /// The server to connect to.
+    pub host: Host,
+
+    /// The bucket to upload into.
+    pub bucket: String,
+
+    /// The prefix the keys of the uploaded objects start with, e.g. `vbus/`.
+    pub prefix: String,
+
+    /// The region the requests are signed for, `us-east-1` unless the server needs another one.
+    pub region: String,
+
+    /// The access key ID to sign the requests with.
+    pub access_key: String,
+
+    /// The secret access key to sign the requests with.
+    pub secret_key: String,
+}
+
+impl S3Target {
+    /// Parse a URL given as `[http[s]://]host[:port]/bucket[/prefix]`, leaving the credentials
+    /// empty.
+    pub fn parse(url: &str) -> Result<S3Target> {
+        let scheme_len = url.find("://").map_or(0, |index| index + 3);
+        let (host, path) = match url [scheme_len..].find('/') {
+            Some(index) => url.split_at(scheme_len + index),
+            None => (url, ""),
+        };
+        if host [scheme_len..].contains('@') {
+            return Err(Error::Parse(format!("Pass the credentials of {:?} using the access and secret key instead", url).into()));
+        }
+
+        let (bucket, prefix) = path.trim_start_matches('/').split_once('/').unwrap_or((path.trim_start_matches('/'), ""));
+        if bucket.is_empty() {
+            return Err(Error::Parse(format!("Missing bucket in {:?}", url).into()));
+        }
+        let prefix = match prefix.trim_end_matches('/') {
+            "" => String::new(),
+            prefix => format!("{}/", prefix),
+        };
+
+        Ok(S3Target {
+            host: Host::parse(host, &SyncOptions::default())?,
+            bucket: bucket.to_string(),
+            prefix,
+            region: "us-east-1".to_string(),
+            access_key: String::new(),
+            secret_key: String::new(),
+        })
+    }
+
+    /// The `Authorization` header of an AWS Signature Version 4 signed request, covering the
+    /// `Host`, `x-amz-content-sha256` and `x-amz-date` headers.
+    fn authorization(&self, method: Method, path: &str, headers: &[(&str, String)], payload_hash: &str, timestamp: &str) -> String {
+        let date = &timestamp [0..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+
+        let canonical_headers = headers.iter().map(|(name, value)| format!("{}:{}\n", name.to_ascii_lowercase(), value.trim())).collect::<String>();
+        let signed_headers = headers.iter().map(|(name, _)| name.to_ascii_lowercase()).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", method, path, canonical_headers, signed_headers, payload_hash);
+
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", timestamp, scope, sha256_hex(canonical_request.as_bytes()));
+
+        let mut key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date, &self.region, "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.access_key, scope, signed_headers, signature)
+    }
+}
+
+/// PUT the given output files into an S3 bucket, keyed by the prefix, the name of the host
+/// directory `dir` and their path within it, like `vbus/192.168.1.50/20240401.csv`.
+///
+/// Objects of the same key are replaced, so uploading a day again does no harm.
+pub async fn upload_to_s3(target: &S3Target, dir: &Path, filenames: &[PathBuf], options: &SyncOptions) -> Result<()> {
+    let client = Client::new(&target.host, options);
+
+    let dir_name = dir.file_name().unwrap_or_default().to_string_lossy();
+
+    for filename in filenames {
+        let rel_filename = filename.strip_prefix(dir).unwrap_or(filename);
+        let key = format!("{}{}/{}", target.prefix, dir_name, rel_filename.to_string_lossy().replace('\\', "/"));
+        let path = format!("/{}/{}", uri_encode(&target.bucket), key.split('/').map(uri_encode).collect::<Vec<_>>().join("/"));
+
+        debug!("Uploading {:?} to {:?} as {:?}...", filename, target.host.name, key);
+
+        let body = async_std::fs::read(filename).await?;
+        let payload_hash = sha256_hex(&body);
+        let timestamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let signed_headers = [
+            ("Host", target.host.authority()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        let authorization = target.authorization(Method::Put, &path, &signed_headers, &payload_hash, &timestamp);
+
+        let content_type = match filename.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => "text/csv; charset=utf-8",
+            Some("json") => "application/json",
+            _ => "application/octet-stream",
+        };
+        let mut headers = signed_headers.to_vec();
+        headers.push(("Authorization", authorization));
+        headers.push(("Content-Type", content_type.to_string()));
+
+        let (conn, mut res) = client.send_with_body(Method::Put, &path, &headers, Some(&body), true).await?;
+
+        let what = format!("uploading {:?}", filename);
+        let res_body = read_body_with_progress(&mut res, options, &what).await?;
+        client.release(conn, &res);
+
+        if !res.status().is_success() {
+            let message = String::from_utf8_lossy(&res_body);
+            return Err(Error::Other(format!("Unable to upload {:?}: status {}: {}", filename, res.status(), message.trim())));
+        }
+    }
+
+    Ok(())
+}
+
+/// Percent-encode everything but the unreserved characters, as required to sign a request.
+fn uri_encode(segment: &str) -> String {
+    segment.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_varkey(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
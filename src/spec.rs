@@ -0,0 +1,100 @@
+use std::{borrow::Cow, path::Path};
+
+use chrono::{TimeZone, Utc};
+use resol_vbus::{Header, Language, Packet, Specification, SpecificationFile};
+
+use crate::Result;
+
+/// The contents of the VBus specification file embedded into this crate.
+pub const EMBEDDED_SPECIFICATION_FILE: &[u8] = include_bytes!("../vbus_specification.vsf");
+
+/// A VBus specification file and the language to load it in.
+///
+/// `Specification` is not `Sync`, so this keeps the raw bytes around and every thread that needs
+/// a `Specification` loads its own copy.
+#[derive(Debug, Clone)]
+pub struct SpecSource {
+    bytes: Cow<'static, [u8]>,
+    language: Language,
+}
+
+impl SpecSource {
+    /// Use the specification file embedded into this crate.
+    pub fn embedded(language: Language) -> SpecSource {
+        SpecSource {
+            bytes: Cow::Borrowed(EMBEDDED_SPECIFICATION_FILE),
+            language,
+        }
+    }
+
+    /// Use the contents of another specification file, e.g. a newer one.
+    pub fn from_bytes(bytes: Vec<u8>, language: Language) -> SpecSource {
+        SpecSource {
+            bytes: Cow::Owned(bytes),
+            language,
+        }
+    }
+
+    /// Read another specification file, e.g. one shipped with new device definitions.
+    pub fn from_file(filename: &Path, language: Language) -> Result<SpecSource> {
+        Ok(SpecSource::from_bytes(std::fs::read(filename)?, language))
+    }
+
+    /// The language used for field names and units.
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// The same specification file, loaded in another language.
+    pub fn with_language(&self, language: Language) -> SpecSource {
+        SpecSource {
+            bytes: self.bytes.clone(),
+            language,
+        }
+    }
+
+    /// The date the specification file was created, as `YYYYMMDD`.
+    pub fn datecode(&self) -> Result<i32> {
+        Ok(SpecificationFile::from_bytes(&self.bytes)?.datecode)
+    }
+
+    /// Parse the specification file.
+    pub fn load(&self) -> Result<Specification> {
+        let spec_file = SpecificationFile::from_bytes(&self.bytes)?;
+        Ok(Specification::from_file(spec_file, self.language))
+    }
+
+    /// A packet without frames of the first packet template containing a field of each of the
+    /// given names, matched case-insensitively, or `None` for names not in the specification.
+    pub(crate) fn empty_packets_with_fields(&self, names: &[String]) -> Result<Vec<Option<Packet>>> {
+        let spec_file = SpecificationFile::from_bytes(&self.bytes)?;
+        Ok(names.iter().map(|name| {
+            let name = name.to_lowercase();
+            let template = spec_file.packet_templates.iter().find(|template| template.fields.iter().any(|field| {
+                spec_file.localized_text_by_index(&field.name_localized_text_index, self.language).to_lowercase() == name
+            }))?;
+            Some(Packet {
+                header: Header {
+                    timestamp: Utc.timestamp_opt(0, 0).unwrap(),
+                    channel: 0,
+                    destination_address: template.destination_address,
+                    source_address: template.source_address,
+                    protocol_version: 0x10,
+                },
+                command: template.command,
+                frame_count: 0,
+                frame_data: [0; 508],
+            })
+        }).collect())
+    }
+}
+
+/// Parse a language given as `en`, `de` or `fr`.
+pub fn parse_language(code: &str) -> std::result::Result<Language, String> {
+    match code.to_ascii_lowercase().as_str() {
+        "en" => Ok(Language::En),
+        "de" => Ok(Language::De),
+        "fr" => Ok(Language::Fr),
+        _ => Err(format!("Unknown language {:?}, expected en, de or fr", code)),
+    }
+}
@@ -0,0 +1,621 @@
+use std::{collections::{BTreeMap, HashSet}, io::Read, path::Path, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+
+use async_std::fs::create_dir_all;
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use futures::{stream, StreamExt};
+use http_types::{Method, Response, StatusCode};
+use resol_vbus::{recording_decoder::length_from_bytes, StreamBlobLength};
+use tracing::{debug, error, info, warn};
+
+use crate::{atomic::write_file_atomically, checksum::{checksum_matches, store_checksum}, client::{read_body_with_progress, with_timeout, Client}, disk_space::available_space, index::{parse_log_index, parse_next_page_link, LogFileEntry}, index_cache::IndexCache, validators::Validators, DateRange, Error, Host, Result};
+
+/// Options controlling how log files are downloaded.
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Connect to all hosts using TLS, even if given without `https://` scheme.
+    pub tls: bool,
+
+    /// Accept self-signed or otherwise invalid TLS certificates.
+    pub accept_invalid_certs: bool,
+
+    /// Username for HTTP Basic Auth, unless given as part of the host.
+    pub username: Option<String>,
+
+    /// Password for HTTP Basic Auth, unless given as part of the host.
+    pub password: Option<String>,
+
+    /// Number of times a failed request is retried.
+    pub retries: u32,
+
+    /// Delay before the first retry, doubled for every subsequent one.
+    pub retry_delay: Duration,
+
+    /// Maximum time to wait for a connection, a response or a response body.
+    pub timeout: Duration,
+
+    /// Maximum number of log files downloaded simultaneously from a host.
+    pub concurrency: usize,
+
+    /// Maximum number of requests per second sent to a host, unlimited if `None`.
+    pub max_request_rate: Option<f64>,
+
+    /// The `User-Agent` header sent with every request.
+    pub user_agent: String,
+
+    /// Only download the log files needed to convert the days within this range.
+    pub date_range: DateRange,
+
+    /// Check that log files consist of valid VBus records instead of trusting their size,
+    /// downloading them again otherwise.
+    pub verify: bool,
+
+    /// Only log which log files would be downloaded, without requesting or storing them.
+    pub dry_run: bool,
+
+    /// Number of bytes that must remain free on the filesystem of the host directory after
+    /// storing a log file, which is not downloaded otherwise.
+    pub min_free_space: u64,
+
+    /// Maximum number of pages of a log directory index split into several to read, unlimited
+    /// if `None`, e.g. to only check the recent log files on devices listing the newest first.
+    pub max_index_pages: Option<usize>,
+
+    /// Once set, no further log files are downloaded, e.g. after Ctrl-C was pressed.
+    pub interrupted: Arc<AtomicBool>,
+}
+
+/// The `User-Agent` header sent unless given otherwise, like `vbus-sync/0.2.0`.
+pub const DEFAULT_USER_AGENT: &str = concat!("vbus-sync/", env!("CARGO_PKG_VERSION"));
+
+impl Default for SyncOptions {
+    fn default() -> SyncOptions {
+        SyncOptions {
+            tls: false,
+            accept_invalid_certs: false,
+            username: None,
+            password: None,
+            retries: 3,
+            retry_delay: Duration::from_secs(1),
+            timeout: Duration::from_secs(30),
+            concurrency: 2,
+            max_request_rate: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            date_range: DateRange::default(),
+            verify: false,
+            dry_run: false,
+            min_free_space: 0,
+            max_index_pages: None,
+            interrupted: Arc::default(),
+        }
+    }
+}
+
+
+/// The outcome of syncing a host.
+#[derive(Debug)]
+pub struct SyncReport {
+    /// Number of log files listed by the host.
+    pub available: usize,
+
+    /// Datecodes of the log files that were downloaded or resumed.
+    pub downloaded: Vec<String>,
+
+    /// Number of bytes received for the downloaded log files.
+    pub bytes_downloaded: u64,
+
+    /// Whether syncing was interrupted before all log files were checked.
+    pub interrupted: bool,
+}
+
+/// Download all log files of a host into `dir`, skipping those that are already up to date.
+///
+/// The files are usually stored in a directory named by `Host::dir_name`.
+pub async fn sync(host: &Host, dir: &Path, options: &SyncOptions) -> Result<SyncReport> {
+    debug!("Downloading log file index for {:?}", host.name);
+
+    let client = Client::new(host, options);
+
+    let cache = IndexCache::load(dir);
+
+    let headers = match cache.as_ref().and_then(|cache| cache.etag.as_ref()) {
+        Some(etag) => vec![("If-None-Match", etag.clone())],
+        None => Vec::new(),
+    };
+
+    let (conn, mut res) = client.send_with_headers(Method::Get, &host.log_path, &headers).await?;
+
+    host.check_authorized(&res)?;
+    let (body, etag) = match cache {
+        Some(ref cache) if res.status() == StatusCode::NotModified => {
+            client.release(conn, &res);
+            (cache.body.clone(), cache.etag.clone())
+        }
+        _ => {
+            if !res.status().is_success() {
+                return Err("Unable to download log directory index".into());
+            }
+
+            let what = "downloading log directory index".to_string();
+            let body = with_timeout(options.timeout, what, async { Ok(res.body_string().await?) }).await?;
+
+            client.release(conn, &res);
+
+            (body, res.header("etag").map(|value| value.as_str().to_string()))
+        }
+    };
+
+    // Some firmwares split the index of devices with many log files into several pages
+    let pages = fetch_following_index_pages(&client, &body, options).await?;
+
+    let entries = parse_index_pages(&body, &pages);
+
+    // The log files listed are only known to be unchanged if the index is, or if they are listed
+    // with the same size as before, since the newest one changes the index as it grows
+    let complete = match cache {
+        Some(cache) if cache.body == body && cache.pages == pages => cache.complete,
+        Some(cache) => {
+            let listed = entries.iter().filter(|entry| entry.size.is_some()).collect::<HashSet<_>>();
+            let cached_entries = parse_index_pages(&cache.body, &cache.pages);
+            let unchanged = cached_entries.iter().filter(|entry| listed.contains(entry)).map(|entry| entry.datecode.as_str()).collect::<HashSet<_>>();
+            cache.complete.into_iter().filter(|(datecode, _)| unchanged.contains(datecode.as_str())).collect()
+        }
+        None => BTreeMap::new(),
+    };
+    let mut cache = IndexCache {
+        etag,
+        body,
+        pages,
+        complete,
+    };
+
+    // debug!(%body);
+
+    if !options.dry_run {
+        create_dir_all(dir).await?;
+    }
+
+    // Local days start or end on the neighbouring day in UTC, which the files are named after
+    let date_range = options.date_range.widened(1);
+
+    // The device may still be appending to the newest log file
+    let newest_datecode = entries.iter().map(|entry| entry.datecode.clone()).max();
+
+    let entries = entries.into_iter()
+        .filter(|entry| date_range.contains_datecode(&entry.datecode))
+        .collect::<Vec<_>>();
+
+    // Verifying the log files requires reading them all
+    let (unchanged, changed): (Vec<_>, Vec<_>) = entries.iter().partition(|entry| {
+        !options.verify && Some(&entry.datecode) != newest_datecode.as_ref() && cache.is_complete(&entry.datecode, &dir.join(format!("{}.vbus", entry.datecode)))
+    });
+    if !unchanged.is_empty() {
+        debug!("Skipping {} log files that were up to date and are listed unchanged", unchanged.len());
+    }
+
+    let client = &client;
+    let results = stream::iter(changed)
+        .map(|entry| async move {
+            let result = if options.interrupted.load(Ordering::SeqCst) {
+                Err(Error::Interrupted)
+            } else {
+                sync_for_datecode(client, dir, &entry.datecode, entry.size).await
+            };
+            (&entry.datecode, result)
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut report = SyncReport {
+        available: entries.len(),
+        downloaded: Vec::new(),
+        bytes_downloaded: 0,
+        interrupted: false,
+    };
+
+    let mut error_count = 0;
+    for (datecode, result) in results {
+        match &result {
+            Ok(Some(bytes)) => {
+                report.downloaded.push(datecode.to_string());
+                report.bytes_downloaded += *bytes;
+            }
+            Ok(None) => {}
+            Err(Error::Interrupted) => report.interrupted = true,
+            Err(err) => {
+                error!("Unable to sync log file dated {}: {}", datecode, err);
+                error_count += 1;
+            }
+        }
+
+        match (&result, std::fs::metadata(dir.join(format!("{}.vbus", datecode)))) {
+            (Ok(_), Ok(metadata)) => cache.complete.insert(datecode.to_string(), metadata.len()),
+            _ => cache.complete.remove(datecode),
+        };
+    }
+
+    if !options.dry_run {
+        cache.store(dir)?;
+    }
+
+    if error_count > 0 {
+        return Err(format!("Unable to sync {} of {} log files", error_count, entries.len()).into());
+    }
+
+    report.downloaded.sort();
+
+    Ok(report)
+}
+
+/// The log files listed on the pages of an index, each only once.
+fn parse_index_pages(body: &str, pages: &[String]) -> Vec<LogFileEntry> {
+    let mut entries = parse_log_index(body);
+    let mut datecodes = entries.iter().map(|entry| entry.datecode.clone()).collect::<HashSet<_>>();
+    for page in pages {
+        entries.extend(parse_log_index(page).into_iter().filter(|entry| datecodes.insert(entry.datecode.clone())));
+    }
+    entries
+}
+
+/// Download the pages following the first one of a log directory index that is split into
+/// several, up to `max_index_pages` pages in total.
+async fn fetch_following_index_pages(client: &Client<'_>, first_page: &str, options: &SyncOptions) -> Result<Vec<String>> {
+    let host = client.host;
+
+    let mut pages: Vec<String> = Vec::new();
+    let mut path = host.log_path.clone();
+    let mut visited = vec![path.clone()];
+    loop {
+        let page = pages.last().map_or(first_page, String::as_str);
+        let Some(link) = parse_next_page_link(page) else {
+            break;
+        };
+        if options.max_index_pages.is_some_and(|max_index_pages| pages.len() + 1 >= max_index_pages) {
+            debug!("Not following further pages of the log directory index");
+            break;
+        }
+
+        let url = host.url(&path)?.join(&link)?;
+        if url.origin() != host.url("/")?.origin() {
+            warn!("Not following link to next page of log directory index to another host: {}", url);
+            break;
+        }
+        path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        // Some firmwares link the last page to itself
+        if visited.contains(&path) {
+            break;
+        }
+        visited.push(path.clone());
+
+        debug!("Downloading page {} of log file index for {:?}", pages.len() + 2, host.name);
+
+        let (conn, mut res) = client.send(Method::Get, &path).await?;
+
+        host.check_authorized(&res)?;
+        if !res.status().is_success() {
+            return Err(format!("Unable to download page {} of log directory index", pages.len() + 2).into());
+        }
+
+        let what = "downloading log directory index".to_string();
+        let body = with_timeout(options.timeout, what, async { Ok(res.body_string().await?) }).await?;
+
+        client.release(conn, &res);
+
+        pages.push(body);
+    }
+    Ok(pages)
+}
+
+/// Download a single log file into memory without storing it, e.g. to decode it using
+/// `convert_to_records`.
+pub async fn fetch_log_file(host: &Host, datecode: &str, options: &SyncOptions) -> Result<Vec<u8>> {
+    let client = Client::new(host, options);
+
+    let (conn, mut res) = client.send(Method::Get, &host.log_file_path(datecode)).await?;
+
+    host.check_authorized(&res)?;
+    if !res.status().is_success() {
+        return Err(format!("Unable to download log file dated {}", datecode).into());
+    }
+
+    let what = format!("downloading log file dated {}", datecode);
+    let body = read_body_with_progress(&mut res, options, &what).await?;
+
+    client.release(conn, &res);
+
+    decode_body(body, content_encoding(&res).as_deref())
+}
+
+/// Request the size of a log file using a HEAD request.
+///
+/// Returns `None` if the response is compressed, since its length is not the one of the file then,
+/// or if it has no `Content-Length`, e.g. because the device sends the file chunked.
+async fn fetch_file_size(client: &Client<'_>, datecode: &str) -> Result<Option<u64>> {
+    debug!("Fetching information about log file dated {}", datecode);
+
+    let (conn, res) = client.send(Method::Head, &client.host.log_file_path(datecode)).await?;
+
+    client.release(conn, &res);
+
+    client.host.check_authorized(&res)?;
+    if !res.status().is_success() {
+        return Err(format!("Unable to download log file dated {}", datecode).into());
+    }
+
+    // debug!(?res);
+
+    if content_encoding(&res).is_some() {
+        debug!("Unable to determine file size dated {}, since the response is compressed", datecode);
+        Ok(None)
+    } else if let Some(content_length) = res.header("content-length") {
+        Ok(Some(content_length.as_str().parse::<u64>()?))
+    } else {
+        debug!("Unable to determine file size dated {}, since the response has no content length", datecode);
+        Ok(None)
+    }
+}
+
+/// Fail unless storing `size` bytes in `dir` leaves at least `min_free_space` bytes free.
+fn check_free_space(dir: &Path, datecode: &str, size: u64, options: &SyncOptions) -> Result<()> {
+    let Some(available) = available_space(dir) else {
+        return Ok(());
+    };
+    let needed = size.saturating_add(options.min_free_space);
+    if available < needed {
+        return Err(format!("Not enough free space to store log file dated {}: {} bytes available in {:?}, {} bytes needed", datecode, available, dir, needed).into());
+    }
+    Ok(())
+}
+
+/// Download a single log file, returning the number of bytes received if it was changed.
+///
+/// The size listed in the index is used if given, saving a HEAD request.
+async fn sync_for_datecode(client: &Client<'_>, dir: &Path, datecode: &str, listed_size: Option<u64>) -> Result<Option<u64>> {
+    let options = client.options;
+
+    let vbus_filename = dir.join(format!("{}.vbus", datecode));
+
+    let content_length = match listed_size {
+        Some(size) => Some(size),
+        None => fetch_file_size(client, datecode).await?,
+    };
+
+    // debug!(?content_length);
+
+    let mut file_size = if let Ok(metadata) = std::fs::metadata(&vbus_filename) {
+        metadata.len()
+    } else {
+        0
+    };
+
+    // Neither a matching size nor a prefix to resume from can be trusted if the file is broken
+    if file_size > 0 {
+        let contents = std::fs::read(&vbus_filename)?;
+        if checksum_matches(&vbus_filename, &contents) == Some(false) {
+            warn!("Downloading log file dated {} again, since it does not match its checksum", datecode);
+            file_size = 0;
+        } else if options.verify && !is_valid_recording(&contents) {
+            warn!("Downloading log file dated {} again, since it is not a valid VBus recording", datecode);
+            file_size = 0;
+        }
+    }
+
+    // Only ask whether the file changed if the local one is worth keeping
+    let validators = if file_size > 0 {
+        Validators::load(&vbus_filename)
+    } else {
+        None
+    };
+
+    // Some devices store log files compressed and list that size, so compare it instead and
+    // never resume, since the offsets of the stored file do not apply to the local one
+    let compressed_size = validators.as_ref().and_then(|validators| validators.compressed_size);
+    let resume_size = if compressed_size.is_some() { 0 } else { file_size };
+
+    // The device decides whether a file it served validators for changed, even if it was
+    // rewritten at the same size, while the sizes decide otherwise. Files of unknown size are
+    // always requested, unless the device confirms they are unchanged. A dry run sends no
+    // requests, so only the sizes are compared
+    let has_conditions = validators.as_ref().is_some_and(Validators::has_conditions);
+    let needs_download = content_length != Some(compressed_size.unwrap_or(file_size)) || (has_conditions && !options.dry_run);
+
+    // debug!(?needs_download);
+
+    if needs_download && options.dry_run {
+        let size = match content_length {
+            Some(content_length) if resume_size > 0 && resume_size < content_length => format!("{} of {} bytes", content_length - resume_size, content_length),
+            Some(content_length) => format!("{} bytes", content_length),
+            None => "unknown size".to_string(),
+        };
+        info!("Would download log file dated {} ({})", datecode, size);
+        return Ok(None);
+    }
+
+    // The whole file is written again, even if resumed, so it must fit next to the old one
+    if needs_download {
+        if let Some(content_length) = content_length {
+            check_free_space(dir, datecode, content_length, options)?;
+        }
+    }
+
+    let download = if needs_download {
+        download_log_file(client, &vbus_filename, datecode, resume_size, content_length, validators.as_ref()).await?
+    } else {
+        None
+    };
+
+    // Without a size to compare to, a download can only be checked by parsing it
+    let check_recording = options.verify || content_length.is_none();
+
+    let bytes_downloaded = if let Some(mut download) = download {
+        if check_recording && !is_valid_recording(&download.contents) {
+            warn!("Downloading log file dated {} again, since the download is not a valid VBus recording", datecode);
+
+            download = match download_log_file(client, &vbus_filename, datecode, 0, content_length, None).await? {
+                Some(download) if is_valid_recording(&download.contents) => download,
+                _ => return Err(format!("Downloaded log file dated {} is not a valid VBus recording", datecode).into()),
+            };
+        }
+
+        // Files of unknown size are downloaded on every run, as are files whose validators the
+        // device ignores, so keep them untouched if unchanged so that their days are not
+        // converted again
+        let is_unchanged = (content_length.is_none() || has_conditions) && file_size == download.contents.len() as u64 && std::fs::read(&vbus_filename)? == download.contents;
+
+        if is_unchanged {
+            debug!("Log file dated {} is unchanged", datecode);
+        } else {
+            // Concurrent downloads may have used up the space, or the size was not known up front
+            check_free_space(dir, datecode, download.contents.len() as u64, options)?;
+            write_file_atomically(&vbus_filename, &download.contents)?;
+        }
+        store_checksum(&vbus_filename, &download.contents)?;
+        download.validators.store(&vbus_filename)?;
+
+        (!is_unchanged).then_some(download.bytes_downloaded)
+    } else {
+        debug!("Skipping download for file dated {}", datecode);
+
+        None
+    };
+
+    Ok(bytes_downloaded)
+}
+
+/// A log file received from the device.
+struct Download {
+    /// The complete contents of the file.
+    contents: Vec<u8>,
+
+    /// The number of bytes received.
+    bytes_downloaded: u64,
+
+    /// The headers to ask whether the file changed next time.
+    validators: Validators,
+}
+
+/// Download a log file, resuming after the `file_size` bytes stored locally if possible.
+///
+/// If `validators` of the local file are given, returns `None` if the device responds that the
+/// file was not modified since.
+async fn download_log_file(client: &Client<'_>, vbus_filename: &Path, datecode: &str, file_size: u64, content_length: Option<u64>, validators: Option<&Validators>) -> Result<Option<Download>> {
+    let host = client.host;
+    let options = client.options;
+
+    // Log files are only ever appended to, so a smaller local file is a valid prefix
+    let resume = file_size > 0 && content_length.is_some_and(|content_length| file_size < content_length);
+
+    let mut headers = validators.map(Validators::conditional_headers).unwrap_or_default();
+
+    // Ask proxies not to compress the range, since that could not be appended to the file
+    if resume {
+        headers.push(("Range", format!("bytes={}-", file_size)));
+        headers.push(("Accept-Encoding", "identity".to_string()));
+    }
+
+    let (conn, mut res) = client.send_with_headers(Method::Get, &host.log_file_path(datecode), &headers).await?;
+
+    host.check_authorized(&res)?;
+    if validators.is_some() && res.status() == StatusCode::NotModified {
+        client.release(conn, &res);
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(format!("Unable to download log file dated {}", datecode).into());
+    }
+
+    let is_partial = resume && res.status() == StatusCode::PartialContent;
+
+    if is_partial {
+        let expected_range_prefix = format!("bytes {}-", file_size);
+        let is_expected_range = match res.header("content-range") {
+            Some(content_range) => content_range.as_str().starts_with(&expected_range_prefix),
+            None => false,
+        };
+        if !is_expected_range {
+            return Err(format!("Unexpected content range for log file dated {}", datecode).into());
+        }
+        if content_encoding(&res).is_some() {
+            return Err(format!("Unable to resume compressed download of log file dated {}", datecode).into());
+        }
+    }
+
+    let what = format!("downloading log file dated {}", datecode);
+    let body = read_body_with_progress(&mut res, options, &what).await?;
+
+    client.release(conn, &res);
+
+    let bytes_downloaded = body.len() as u64;
+
+    let encoding = content_encoding(&res);
+    let body = decode_body(body, encoding.as_deref())?;
+
+    let mut validators = Validators::from_response(&res);
+    // Unlike a reverse proxy compressing on the fly, such devices list the compressed size
+    if encoding.is_some() && !is_partial && content_length == Some(bytes_downloaded) && body.len() as u64 != bytes_downloaded {
+        debug!("Log file dated {} is stored compressed on the device ({} bytes)", datecode, bytes_downloaded);
+        validators.compressed_size = Some(bytes_downloaded);
+    }
+
+    let contents = if is_partial {
+        debug!("Appending {} bytes to file dated {}", body.len(), datecode);
+
+        let mut contents = async_std::fs::read(vbus_filename).await?;
+        contents.extend_from_slice(&body);
+        contents
+    } else {
+        body
+    };
+
+    Ok(Some(Download {
+        contents,
+        bytes_downloaded,
+        validators,
+    }))
+}
+
+/// The `Content-Encoding` of a response, e.g. applied by a reverse proxy, unless it is `identity`.
+fn content_encoding(res: &Response) -> Option<String> {
+    let encoding = res.header("content-encoding")?.as_str().trim().to_ascii_lowercase();
+    (encoding != "identity").then_some(encoding)
+}
+
+/// Decompress a body sent using the given `Content-Encoding`.
+fn decode_body(body: Vec<u8>, encoding: Option<&str>) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    match encoding {
+        None => return Ok(body),
+        Some("gzip" | "x-gzip") => {
+            GzDecoder::new(&body [..]).read_to_end(&mut decoded)?;
+        }
+        Some("deflate") => {
+            // Servers disagree whether this means a zlib stream or raw deflate data
+            if ZlibDecoder::new(&body [..]).read_to_end(&mut decoded).is_err() {
+                decoded.clear();
+                DeflateDecoder::new(&body [..]).read_to_end(&mut decoded)?;
+            }
+        }
+        Some(encoding) => return Err(format!("Unsupported content encoding {:?}", encoding).into()),
+    }
+    Ok(decoded)
+}
+
+/// Whether the bytes consist of VBus records only.
+///
+/// `RecordingReader` silently skips anything else, so an error page served in place of a log
+/// file would be taken for an empty one.
+fn is_valid_recording(bytes: &[u8]) -> bool {
+    let mut offset = 0;
+    while offset < bytes.len() {
+        match length_from_bytes(&bytes [offset..]) {
+            StreamBlobLength::BlobLength(length) => offset += length,
+            // The device may still be writing the last record
+            StreamBlobLength::Partial => break,
+            StreamBlobLength::Malformed => return false,
+        }
+    }
+    true
+}
@@ -0,0 +1,163 @@
+use std::io::{self, Read, Write};
+
+use chrono::DateTime;
+use resol_vbus::{recording_decoder::length_from_bytes, StreamBlobLength};
+
+use crate::{Error, Result};
+
+/// Number of bytes requested from the input at once.
+const READ_SIZE: usize = 64 * 1024;
+
+/// Reads the records of `.vbus` data that `RecordingReader` is able to decode, skipping the
+/// corrupt ones and calling `corrupt` with the offset of and the reason for each of them.
+///
+/// Only the current record and the bytes following it are buffered, which is at most one
+/// record of up to 64 KiB plus a read, however large the input is.
+pub(crate) struct ValidRecords<R, F> {
+    input: R,
+    corrupt: F,
+    buf: Vec<u8>,
+    /// The offset of the first buffered byte within the input.
+    buf_offset: usize,
+    /// The start of the buffered bytes that were not checked yet.
+    pos: usize,
+    /// The part of a valid record that was not read yet.
+    record: (usize, usize),
+    /// Whether the bytes are skipped up to the next one that may start a record.
+    skipping: bool,
+    eof: bool,
+    error: Option<Error>,
+}
+
+impl<R: Read, F: FnMut(usize, &str) -> Result<()>> ValidRecords<R, F> {
+    pub(crate) fn new(input: R, corrupt: F) -> ValidRecords<R, F> {
+        ValidRecords {
+            input,
+            corrupt,
+            buf: Vec::new(),
+            buf_offset: 0,
+            pos: 0,
+            record: (0, 0),
+            skipping: false,
+            eof: false,
+            error: None,
+        }
+    }
+
+    /// Drop the bytes already handled and read more, returning whether there were any.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buf.drain(..self.pos);
+        self.buf_offset += self.pos;
+        self.pos = 0;
+        self.record = (0, 0);
+
+        let len = self.buf.len();
+        self.buf.resize(len + READ_SIZE, 0);
+        let result = loop {
+            match self.input.read(&mut self.buf [len..]) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        let count = *result.as_ref().unwrap_or(&0);
+        self.buf.truncate(len + count);
+        result?;
+
+        self.eof = count == 0;
+        Ok(!self.eof)
+    }
+}
+
+impl<R: Read, F: FnMut(usize, &str) -> Result<()>> Read for ValidRecords<R, F> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let (start, end) = self.record;
+            if start < end {
+                let count = out.len().min(end - start);
+                out [..count].copy_from_slice(&self.buf [start..start + count]);
+                self.record.0 += count;
+                return Ok(count);
+            }
+
+            if self.skipping {
+                match self.buf [self.pos..].iter().position(|byte| *byte == 0xA5) {
+                    Some(index) => {
+                        self.pos += index;
+                        self.skipping = false;
+                    }
+                    None => {
+                        self.pos = self.buf.len();
+                        if self.eof || !self.fill()? {
+                            return Ok(0);
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            let bytes = &self.buf [self.pos..];
+            let reason = match length_from_bytes(bytes) {
+                StreamBlobLength::BlobLength(length) => match check_record(&bytes [..length]) {
+                    Ok(()) => {
+                        self.record = (self.pos, self.pos + length);
+                        self.pos += length;
+                        continue;
+                    }
+                    Err(reason) => reason,
+                },
+                StreamBlobLength::Malformed => "malformed record",
+                StreamBlobLength::Partial if !self.eof => {
+                    self.fill()?;
+                    continue;
+                }
+                // The device may still be writing the last record
+                StreamBlobLength::Partial if bytes.is_empty() || !contains_record(&bytes [1..]) => return Ok(0),
+                StreamBlobLength::Partial => "truncated record",
+            };
+
+            if let Err(err) = (self.corrupt)(self.buf_offset + self.pos, reason) {
+                self.error = Some(err);
+                return Err(io::Error::other("Too many corrupt records"));
+            }
+
+            // Continue with the next byte that may start a record
+            self.pos += 1;
+            self.skipping = true;
+        }
+    }
+}
+
+/// Copy the records read from `input` that `RecordingReader` is able to decode to `output`,
+/// calling `corrupt` with the offset of and the reason for each corrupt one skipped.
+pub(crate) fn copy_valid_records(input: impl Read, output: &mut impl Write, corrupt: impl FnMut(usize, &str) -> Result<()>) -> Result<()> {
+    let mut records = ValidRecords::new(input, corrupt);
+    match io::copy(&mut records, output) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(records.error.take().unwrap_or(err.into())),
+    }
+}
+
+/// Whether a complete record starts anywhere within the bytes.
+fn contains_record(bytes: &[u8]) -> bool {
+    (0..bytes.len()).any(|offset| bytes [offset] == 0xA5 && matches!(length_from_bytes(&bytes [offset..]), StreamBlobLength::BlobLength(_)))
+}
+
+/// Check that a complete record can be decoded by `RecordingReader`, which panics on some kinds
+/// of corruption instead of returning an error.
+fn check_record(record: &[u8]) -> std::result::Result<(), &'static str> {
+    if !matches!(record [1], 0x44 | 0x66 | 0x77) {
+        return Err("unknown record type");
+    }
+
+    let timestamp = i64::from_le_bytes(record [6..14].try_into().unwrap());
+    if timestamp < 0 || DateTime::from_timestamp_millis(timestamp).is_none() {
+        return Err("invalid timestamp");
+    }
+
+    // Packets have at most 127 frames of four bytes
+    if record [1] == 0x66 && record.len() >= 26 && record [18] & 0xF0 == 0x10 && u16::from_le_bytes([record [22], record [23]]) > 508 {
+        return Err("invalid frame data length");
+    }
+
+    Ok(())
+}
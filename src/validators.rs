@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use http_types::Response;
+
+use crate::{atomic::write_file_atomically, Result};
+
+/// The `ETag` and `Last-Modified` headers a log file was served with, stored in a
+/// `<DATECODE>.vbus.meta` file next to it to ask the device whether it changed since.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+
+    /// The size of the log file as stored on the device, if it stores it compressed and lists
+    /// that size in the index, while the decompressed contents are stored locally.
+    pub(crate) compressed_size: Option<u64>,
+}
+
+impl Validators {
+    pub(crate) fn from_response(res: &Response) -> Validators {
+        Validators {
+            etag: res.header("etag").map(|value| value.as_str().to_string()),
+            last_modified: res.header("last-modified").map(|value| value.as_str().to_string()),
+            compressed_size: None,
+        }
+    }
+
+    /// The file storing the validators of a `.vbus` file.
+    pub(crate) fn filename(vbus_filename: &Path) -> PathBuf {
+        let mut filename = vbus_filename.as_os_str().to_owned();
+        filename.push(".meta");
+        PathBuf::from(filename)
+    }
+
+    /// Read the validators stored for a `.vbus` file, if any.
+    pub(crate) fn load(vbus_filename: &Path) -> Option<Validators> {
+        let contents = std::fs::read_to_string(Validators::filename(vbus_filename)).ok()?;
+
+        let mut validators = Validators::default();
+        for line in contents.lines() {
+            match line.split_once(": ") {
+                Some(("ETag", value)) => validators.etag = Some(value.to_string()),
+                Some(("Last-Modified", value)) => validators.last_modified = Some(value.to_string()),
+                Some(("Compressed-Size", value)) => validators.compressed_size = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        (!validators.is_empty()).then_some(validators)
+    }
+
+    /// Store the validators for a `.vbus` file, removing outdated ones if there are none.
+    pub(crate) fn store(&self, vbus_filename: &Path) -> Result<()> {
+        let filename = Validators::filename(vbus_filename);
+        if self.is_empty() {
+            if filename.exists() {
+                std::fs::remove_file(filename)?;
+            }
+            return Ok(());
+        }
+
+        let mut contents = String::new();
+        if let Some(etag) = &self.etag {
+            contents.push_str(&format!("ETag: {}\n", etag));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            contents.push_str(&format!("Last-Modified: {}\n", last_modified));
+        }
+        if let Some(compressed_size) = self.compressed_size {
+            contents.push_str(&format!("Compressed-Size: {}\n", compressed_size));
+        }
+        write_file_atomically(&filename, contents.as_bytes())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none() && self.compressed_size.is_none()
+    }
+
+    /// Whether there is an `ETag` or `Last-Modified` value to ask the server whether the file
+    /// changed.
+    pub(crate) fn has_conditions(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    /// The headers asking the server to respond with 304 Not Modified if the file is unchanged.
+    pub(crate) fn conditional_headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if let Some(etag) = &self.etag {
+            headers.push(("If-None-Match", etag.clone()));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            headers.push(("If-Modified-Since", last_modified.clone()));
+        }
+        headers
+    }
+}
@@ -0,0 +1,114 @@
+use std::{str::FromStr, time::Duration};
+
+use http_types::Method;
+
+use crate::{client::{read_body_with_progress, Client}, Error, Host, Result, SyncOptions};
+
+/// How many data sets are sent per request to a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WebhookBatch {
+    /// Send all data sets of a day in a single request.
+    #[default]
+    Day,
+
+    /// Send a request per data set.
+    DataSet,
+}
+
+impl FromStr for WebhookBatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<WebhookBatch, String> {
+        match s {
+            "day" => Ok(WebhookBatch::Day),
+            "data-set" | "record" => Ok(WebhookBatch::DataSet),
+            _ => Err(format!("Unknown webhook batch {:?}, expected day or data-set", s)),
+        }
+    }
+}
+
+/// Options for POSTing the converted data sets as JSON to an HTTP endpoint.
+///
+/// A request is only retried if it could not be sent. Once sent, it is not retried after a 5xx
+/// response or a timeout, since the endpoint may already have processed it, so that it does not
+/// receive the same data sets twice. The day is sent again during the next run instead.
+#[derive(Debug, Clone)]
+pub struct WebhookOptions {
+    /// The URL of the endpoint, given as `[http[s]://][user[:pass]@]host[:port][/path]`.
+    pub url: String,
+
+    /// Additional headers sent with every request, e.g. `Authorization`.
+    pub headers: Vec<(String, String)>,
+
+    /// How many data sets are sent per request.
+    pub batch: WebhookBatch,
+
+    /// Maximum time to wait for a connection, a response or a response body.
+    pub timeout: Duration,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> WebhookOptions {
+        WebhookOptions {
+            url: String::new(),
+            headers: Vec::new(),
+            batch: WebhookBatch::default(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The endpoint of a webhook, parsed from `WebhookOptions::url`.
+pub(crate) struct WebhookTarget {
+    pub(crate) host: Host,
+    pub(crate) path: String,
+    pub(crate) sync_options: SyncOptions,
+}
+
+impl WebhookTarget {
+    pub(crate) fn parse(options: &WebhookOptions) -> Result<WebhookTarget> {
+        let url = &options.url;
+        let scheme_len = url.find("://").map_or(0, |index| index + 3);
+        let (host, path) = match url [scheme_len..].find(['/', '?']) {
+            Some(index) => url.split_at(scheme_len + index),
+            None => (url.as_str(), "/"),
+        };
+
+        let path = if path.starts_with('?') {
+            format!("/{}", path)
+        } else {
+            path.to_string()
+        };
+
+        let sync_options = SyncOptions {
+            timeout: options.timeout,
+            ..SyncOptions::default()
+        };
+
+        Ok(WebhookTarget {
+            host: Host::parse(host, &sync_options)?,
+            path,
+            sync_options,
+        })
+    }
+}
+
+/// POST a JSON body to a webhook, failing unless it responds with success.
+pub(crate) async fn post_json(client: &Client<'_>, path: &str, headers: &[(String, String)], body: &[u8]) -> Result<()> {
+    let mut all_headers = vec![("Content-Type", "application/json".to_string())];
+    all_headers.extend(headers.iter().map(|(name, value)| (name.as_str(), value.clone())));
+
+    let (conn, mut res) = client.send_with_body(Method::Post, path, &all_headers, Some(body), false).await?;
+    client.host.check_authorized(&res)?;
+
+    let what = format!("posting to {:?}", client.host.name);
+    let res_body = read_body_with_progress(&mut res, client.options, &what).await?;
+    client.release(conn, &res);
+
+    if !res.status().is_success() {
+        let message = String::from_utf8_lossy(&res_body);
+        return Err(Error::Other(format!("Webhook responded with status {}: {}", res.status(), message.trim())));
+    }
+
+    Ok(())
+}
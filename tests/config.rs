@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use vbus_sync::{Config, Language};
+
+#[test]
+fn parses_hosts_with_optional_settings() {
+    let config = Config::parse(r#"
+        [[hosts]]
+        host = "d123456789.vbus.io"
+
+        [[hosts]]
+        host = "192.168.1.50:8080"
+        output_dir = "/var/lib/vbus"
+        timezone = "America/New_York"
+        language = "EN"
+        username = "admin"
+        password = "secret"
+    "#).unwrap();
+
+    assert_eq!(config.hosts.len(), 2);
+
+    let host = &config.hosts [0];
+    assert_eq!(host.host, "d123456789.vbus.io");
+    assert!(host.output_dir.is_none());
+    assert!(host.timezone.is_none());
+    assert!(host.language.is_none());
+    assert!(host.username.is_none());
+
+    let host = &config.hosts [1];
+    assert_eq!(host.output_dir.as_deref(), Some(Path::new("/var/lib/vbus")));
+    assert_eq!(host.timezone, Some(chrono_tz::America::New_York));
+    assert!(matches!(host.language, Some(Language::En)));
+    assert_eq!(host.username.as_deref(), Some("admin"));
+    assert_eq!(host.password.as_deref(), Some("secret"));
+}
+
+#[test]
+fn rejects_invalid_settings() {
+    assert!(Config::parse("[[hosts]]\nhost = \"a\"\ntimezone = \"Mars/Olympus\"\n").is_err());
+    assert!(Config::parse("[[hosts]]\nhost = \"a\"\nlanguage = \"es\"\n").is_err());
+    assert!(Config::parse("[[hosts]]\nhost = \"a\"\ntimzone = \"UTC\"\n").is_err());
+    assert!(Config::parse("[[hosts]]\noutput_dir = \".\"\n").is_err());
+}
@@ -0,0 +1,889 @@
+use std::{collections::HashSet, fs, path::{Path, PathBuf}, time::SystemTime};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use resol_vbus::{Data, DataSet, Header, Language, Packet, RecordingWriter};
+use vbus_sync::{convert, convert_dir_to_stream, convert_stream, convert_to_records, ColumnNames, ConvertOptions, DateRange, FieldValue, FilenamePattern, Gap, OutOfOrder, OutputFormat, Resample, ResampleMode, SourceAddresses, SpecSource, RFC3339_TIMESTAMP_FORMAT};
+
+/// Create an empty directory for a test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vbus-sync-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A DeltaSol BS Plus packet with some temperatures.
+fn packet(timestamp: DateTime<Utc>) -> Data {
+    packet_with_temperature(timestamp, 226)
+}
+
+/// A DeltaSol BS Plus packet with the given raw value of its first temperature in 0.1 °C.
+fn packet_with_temperature(timestamp: DateTime<Utc>, temperature: i16) -> Data {
+    let mut frame_data = [0u8; 508];
+    frame_data [0..2].copy_from_slice(&temperature.to_le_bytes());
+    Data::Packet(Packet {
+        header: Header {
+            timestamp,
+            channel: 0,
+            destination_address: 0x0010,
+            source_address: 0x4221,
+            protocol_version: 0x10,
+        },
+        command: 0x0100,
+        frame_count: 4,
+        frame_data,
+    })
+}
+
+/// The timestamps of a data set every ten minutes during the given UTC day.
+fn every_ten_minutes(datecode: &str) -> Vec<DateTime<Utc>> {
+    let date = chrono::NaiveDate::parse_from_str(datecode, "%Y%m%d").unwrap();
+    let start = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+    (0..144).map(|index| start + Duration::minutes(10 * index)).collect()
+}
+
+/// Write a `.vbus` file for the given UTC day containing a data set for each timestamp.
+fn write_vbus_file(dir: &Path, datecode: &str, timestamps: &[DateTime<Utc>]) {
+    let data = timestamps.iter().map(|timestamp| packet(*timestamp)).collect::<Vec<_>>();
+    write_vbus_file_with_data(dir, datecode, &data);
+}
+
+/// Write a `.vbus` file for the given UTC day containing a data set for each packet.
+fn write_vbus_file_with_data(dir: &Path, datecode: &str, data: &[Data]) {
+    let mut bytes = Vec::new();
+    let mut writer = RecordingWriter::new(&mut bytes);
+    for data in data {
+        let mut data_set = DataSet::new();
+        data_set.timestamp = data.as_header().timestamp;
+        data_set.add_data(data.clone());
+        writer.write_data_set(&data_set).unwrap();
+    }
+
+    fs::write(dir.join(format!("{}.vbus", datecode)), &bytes).unwrap();
+}
+
+/// Write a `.vbus` file for each given UTC day with a data set every ten minutes, returning the
+/// number of data sets written.
+fn write_vbus_files(dir: &Path, datecodes: &[&str]) -> usize {
+    let mut count = 0;
+    for datecode in datecodes {
+        let timestamps = every_ten_minutes(datecode);
+        write_vbus_file(dir, datecode, &timestamps);
+        count += timestamps.len();
+    }
+    count
+}
+
+/// The timestamps of all rows in a CSV file.
+fn csv_file_timestamps(filename: &Path) -> Vec<String> {
+    let contents = fs::read_to_string(filename).unwrap();
+    contents.lines().skip(1).map(|line| line.split('\t').next().unwrap().to_string()).collect()
+}
+
+/// The timestamps of all rows in all CSV files of a directory.
+fn csv_timestamps(dir: &Path) -> Vec<String> {
+    let mut filenames = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+        .collect::<Vec<_>>();
+    filenames.sort();
+
+    filenames.iter().flat_map(|filename| csv_file_timestamps(filename)).collect()
+}
+
+fn assert_converted_once(name: &str, timezone: chrono_tz::Tz) {
+    let dir = test_dir(name);
+    // Daylight saving time starts on 2024-03-31 in Europe
+    let count = write_vbus_files(&dir, &["20240330", "20240331", "20240401"]);
+
+    let options = ConvertOptions {
+        timezone,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let timestamps = csv_timestamps(&dir);
+    let unique_timestamps = timestamps.iter().collect::<HashSet<_>>();
+    assert_eq!(timestamps.len(), count);
+    assert_eq!(unique_timestamps.len(), count);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn converts_each_data_set_once_in_utc() {
+    assert_converted_once("utc", chrono_tz::UTC);
+}
+
+#[test]
+fn converts_each_data_set_once_around_dst_transition() {
+    assert_converted_once("london", chrono_tz::Europe::London);
+    assert_converted_once("berlin", chrono_tz::Europe::Berlin);
+}
+
+fn convert_in_berlin(dir: &Path) {
+    let options = ConvertOptions {
+        timezone: chrono_tz::Europe::Berlin,
+        ..ConvertOptions::default()
+    };
+    convert(dir, &SpecSource::embedded(Language::En), &options).unwrap();
+}
+
+#[test]
+fn converts_day_with_23_hours_in_march() {
+    let dir = test_dir("march");
+    write_vbus_files(&dir, &["20240330", "20240401"]);
+
+    // The last data set of the local day is 500ms before local midnight, which is 21:59:59.500 UTC
+    let mut timestamps = every_ten_minutes("20240331");
+    let last_of_day = Utc.with_ymd_and_hms(2024, 3, 31, 21, 59, 59).unwrap() + Duration::milliseconds(500);
+    let index = timestamps.iter().position(|timestamp| *timestamp > last_of_day).unwrap();
+    timestamps.insert(index, last_of_day);
+    write_vbus_file(&dir, "20240331", &timestamps);
+
+    convert_in_berlin(&dir);
+
+    let timestamps = csv_file_timestamps(&dir.join("20240331.csv"));
+    assert_eq!(timestamps.len(), 23 * 6 + 1);
+    assert_eq!(timestamps [0], "31.03.2024 00:00:00");
+    assert_eq!(timestamps [12], "31.03.2024 03:00:00");
+    assert_eq!(timestamps [timestamps.len() - 2], "31.03.2024 23:50:00");
+    assert_eq!(timestamps [timestamps.len() - 1], "31.03.2024 23:59:59");
+
+    let timestamps = csv_file_timestamps(&dir.join("20240401.csv"));
+    assert_eq!(timestamps [0], "01.04.2024 00:00:00");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn converts_day_with_25_hours_in_october() {
+    let dir = test_dir("october");
+    write_vbus_files(&dir, &["20241025", "20241026", "20241027", "20241028"]);
+
+    convert_in_berlin(&dir);
+
+    let timestamps = csv_file_timestamps(&dir.join("20241027.csv"));
+    assert_eq!(timestamps.len(), 25 * 6);
+    assert_eq!(timestamps [0], "27.10.2024 00:00:00");
+    assert_eq!(timestamps.iter().filter(|timestamp| timestamp.as_str() == "27.10.2024 02:30:00").count(), 2);
+    assert_eq!(timestamps [timestamps.len() - 1], "27.10.2024 23:50:00");
+
+    assert_eq!(csv_file_timestamps(&dir.join("20241026.csv")).len(), 24 * 6);
+    assert_eq!(csv_file_timestamps(&dir.join("20241028.csv")) [0], "28.10.2024 00:00:00");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_rfc3339_timestamps_with_offset_around_dst_transition() {
+    let dir = test_dir("rfc3339");
+    write_vbus_files(&dir, &["20241026", "20241027"]);
+
+    let options = ConvertOptions {
+        timestamp_format: RFC3339_TIMESTAMP_FORMAT.to_string(),
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::De), &options).unwrap();
+
+    let timestamps = csv_file_timestamps(&dir.join("20241027.csv"));
+    assert_eq!(timestamps [0], "2024-10-27T00:00:00+02:00");
+    assert_eq!(timestamps.iter().filter(|timestamp| timestamp.starts_with("2024-10-27T02:30:00")).collect::<Vec<_>>(), ["2024-10-27T02:30:00+02:00", "2024-10-27T02:30:00+01:00"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_written_up_to_date_and_empty_days() {
+    let dir = test_dir("report");
+    write_vbus_files(&dir, &["20240330", "20240331"]);
+    write_vbus_file(&dir, "20240401", &[]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        ..ConvertOptions::default()
+    };
+    let spec = SpecSource::embedded(Language::En);
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted.len(), 2);
+    assert_eq!(report.up_to_date, 0);
+    assert_eq!(report.empty, 1);
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted.len(), 0);
+    assert_eq!(report.up_to_date, 2);
+    assert_eq!(report.empty, 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_header_only_file_for_empty_day() {
+    let dir = test_dir("write-empty");
+    write_vbus_files(&dir, &["20240330"]);
+    write_vbus_file(&dir, "20240331", &[]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        write_empty: true,
+        ..ConvertOptions::default()
+    };
+    let spec = SpecSource::embedded(Language::En);
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted.len(), 2);
+    assert_eq!(report.empty, 0);
+
+    let contents = fs::read_to_string(dir.join("20240331.csv")).unwrap();
+    assert_eq!(contents.lines().count(), 1);
+    assert!(contents.starts_with("Date"));
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted.len(), 0);
+    assert_eq!(report.up_to_date, 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_output_files_into_other_directory() {
+    let dir = test_dir("output-dir");
+    write_vbus_files(&dir, &["20240401"]);
+    let output_dir = dir.with_extension("csv");
+    let _ = fs::remove_dir_all(&output_dir);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        output_dir: Some(output_dir.clone()),
+        ..ConvertOptions::default()
+    };
+    let spec = SpecSource::embedded(Language::En);
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    let filename = output_dir.join("20240401.csv");
+    assert_eq!(report.converted, vec![filename.clone()]);
+    assert!(!dir.join("20240401.csv").exists());
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.up_to_date, 1);
+
+    // The output file is compared with the `.vbus` files in the other directory
+    fs::File::options().write(true).open(&filename).unwrap().set_modified(SystemTime::now() - std::time::Duration::from_secs(60)).unwrap();
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted, vec![filename]);
+
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn converts_live_recordings_with_log_file_of_same_day() {
+    let dir = test_dir("live");
+    let timestamps = every_ten_minutes("20240401");
+    let (synced, recorded) = timestamps.split_at(100);
+    write_vbus_file(&dir, "20240401", recorded);
+    fs::rename(dir.join("20240401.vbus"), dir.join("20240401.live.vbus")).unwrap();
+    write_vbus_file(&dir, "20240401", synced);
+    write_vbus_file(&dir, "20240402", &every_ten_minutes("20240402"));
+    fs::rename(dir.join("20240402.vbus"), dir.join("20240402.live.vbus")).unwrap();
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        ..ConvertOptions::default()
+    };
+    let report = convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+    assert_eq!(report.converted, vec![dir.join("20240401.csv"), dir.join("20240402.csv")]);
+
+    let csv_timestamps = csv_timestamps(&dir);
+    assert_eq!(csv_timestamps.len(), 2 * timestamps.len());
+    assert_eq!(csv_timestamps.iter().collect::<HashSet<_>>().len(), csv_timestamps.len());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The first two cells of all rows in a CSV file.
+fn csv_file_first_cells(filename: &Path) -> Vec<(String, String)> {
+    let contents = fs::read_to_string(filename).unwrap();
+    contents.lines().skip(1).map(|line| {
+        let mut cells = line.split('\t');
+        (cells.next().unwrap().to_string(), cells.next().unwrap().to_string())
+    }).collect()
+}
+
+fn convert_resampled(name: &str, mode: ResampleMode) -> Vec<(String, String)> {
+    let dir = test_dir(name);
+    // Alternate between 20.0 °C and 30.0 °C every ten minutes
+    let data = every_ten_minutes("20240401").into_iter().enumerate()
+        .map(|(index, timestamp)| packet_with_temperature(timestamp, if index % 2 == 0 { 200 } else { 300 }))
+        .collect::<Vec<_>>();
+    write_vbus_file_with_data(&dir, "20240401", &data);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        resample: Some(Resample {
+            interval: std::time::Duration::from_secs(3600),
+            mode,
+        }),
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let cells = csv_file_first_cells(&dir.join("20240401.csv"));
+
+    fs::remove_dir_all(&dir).unwrap();
+
+    cells
+}
+
+#[test]
+fn resamples_to_first_data_set_per_interval() {
+    let cells = convert_resampled("resample-first", ResampleMode::First);
+    assert_eq!(cells.len(), 24);
+    assert_eq!(cells [0], ("01.04.2024 00:00:00".to_string(), "20.0".to_string()));
+    assert_eq!(cells [23], ("01.04.2024 23:00:00".to_string(), "20.0".to_string()));
+}
+
+#[test]
+fn resamples_to_mean_of_numbers_per_interval() {
+    let cells = convert_resampled("resample-mean", ResampleMode::Mean);
+    assert_eq!(cells.len(), 24);
+    assert_eq!(cells [0], ("01.04.2024 00:00:00".to_string(), "25.0".to_string()));
+    assert_eq!(cells [23], ("01.04.2024 23:00:00".to_string(), "25.0".to_string()));
+}
+
+#[test]
+fn writes_only_changed_rows() {
+    let dir = test_dir("changes-only");
+    // Constant for the first half of the day, then alternating every ten minutes
+    let data = every_ten_minutes("20240401").into_iter().enumerate()
+        .map(|(index, timestamp)| packet_with_temperature(timestamp, if index < 72 { 200 } else { 200 + (index % 2) as i16 }))
+        .collect::<Vec<_>>();
+    write_vbus_file_with_data(&dir, "20240401", &data);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        changes_only: true,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let cells = csv_file_first_cells(&dir.join("20240401.csv"));
+    // The first row and every row from 12:10 on, when the value first changes
+    assert_eq!(cells.len(), 1 + 71);
+    assert_eq!(cells [0], ("01.04.2024 00:00:00".to_string(), "20.0".to_string()));
+    assert_eq!(cells [1], ("01.04.2024 12:10:00".to_string(), "20.1".to_string()));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_summary_of_numbers_per_day() {
+    let dir = test_dir("summary");
+    let data = every_ten_minutes("20240401").into_iter().enumerate()
+        .map(|(index, timestamp)| packet_with_temperature(timestamp, if index % 2 == 0 { 200 } else { 304 }))
+        .collect::<Vec<_>>();
+    write_vbus_file_with_data(&dir, "20240401", &data);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        summary: true,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let contents = fs::read_to_string(dir.join("20240401_summary.csv")).unwrap();
+    let lines = contents.lines().map(|line| line.split('\t').collect::<Vec<_>>()).collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines [0] [0..4], ["Date", "Temperature sensor 1 min [°C]", "Temperature sensor 1 max [°C]", "Temperature sensor 1 mean [°C]"]);
+    assert_eq!(lines [1] [0..4], ["01.04.2024", "20.0", "30.4", "25.2"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn appends_rows_newer_than_last_row() {
+    let dir = test_dir("append");
+    let timestamps = every_ten_minutes("20240401");
+    write_vbus_file(&dir, "20240401", &timestamps [..72]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        append: true,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    // Mark a row written before, which is only kept if the file is appended to
+    let filename = dir.join("20240401.csv");
+    let contents = fs::read_to_string(&filename).unwrap().replacen("22.6", "marker", 1);
+    fs::write(&filename, contents).unwrap();
+    fs::File::options().write(true).open(&filename).unwrap().set_modified(SystemTime::now() - std::time::Duration::from_secs(60)).unwrap();
+
+    write_vbus_file(&dir, "20240401", &timestamps);
+    let report = convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+    assert_eq!(report.converted, vec![filename.clone()]);
+
+    let contents = fs::read_to_string(&filename).unwrap();
+    assert!(contents.contains("marker"));
+    assert_eq!(contents.lines().filter(|line| line.starts_with("Date")).count(), 1);
+
+    let written = csv_file_timestamps(&filename);
+    assert_eq!(written.len(), timestamps.len());
+    assert_eq!(written.iter().collect::<HashSet<_>>().len(), timestamps.len());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skips_corrupt_records_unless_strict() {
+    let dir = test_dir("corrupt");
+    let timestamps = every_ten_minutes("20240401");
+    write_vbus_file(&dir, "20240401", &timestamps [..72]);
+    let mut bytes = fs::read(dir.join("20240401.vbus")).unwrap();
+    write_vbus_file(&dir, "20240401", &timestamps [72..]);
+    bytes.extend_from_slice(b"\xA5\x44\xFF\xFF\xFF\xFF truncated record");
+    bytes.extend_from_slice(&fs::read(dir.join("20240401.vbus")).unwrap());
+    fs::write(dir.join("20240401.vbus"), bytes).unwrap();
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        max_corrupt_records: Some(0),
+        ..ConvertOptions::default()
+    };
+    assert!(convert(&dir, &SpecSource::embedded(Language::En), &options).is_err());
+
+    let options = ConvertOptions {
+        max_corrupt_records: None,
+        ..options
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+    assert_eq!(csv_file_timestamps(&dir.join("20240401.csv")).len(), timestamps.len());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skips_corrupt_records_in_files_larger_than_read_buffer() {
+    let dir = test_dir("corrupt-large");
+    let start = Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap();
+    let timestamps = (0..8640).map(|index| start + Duration::seconds(10 * index)).collect::<Vec<_>>();
+    write_vbus_file(&dir, "20240401", &timestamps [..4000]);
+    let mut bytes = fs::read(dir.join("20240401.vbus")).unwrap();
+    write_vbus_file(&dir, "20240401", &timestamps [4000..]);
+    bytes.extend_from_slice(b"\xA5\x44\xFF\xFF\xFF\xFF truncated record");
+    bytes.extend_from_slice(&fs::read(dir.join("20240401.vbus")).unwrap());
+    assert!(bytes.len() > 256 * 1024);
+    fs::write(dir.join("20240401.vbus"), bytes).unwrap();
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+    assert_eq!(csv_file_timestamps(&dir.join("20240401.csv")).len(), timestamps.len());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn converts_to_records_in_memory() {
+    let dir = test_dir("records");
+    let timestamps = every_ten_minutes("20240401");
+    write_vbus_file(&dir, "20240401", &timestamps);
+    let bytes = fs::read(dir.join("20240401.vbus")).unwrap();
+
+    let records = convert_to_records(&bytes, &SpecSource::embedded(Language::En), timestamps [6]..timestamps [12]).unwrap();
+    assert_eq!(records.len(), 6);
+    assert_eq!(records [0].timestamp, timestamps [6]);
+
+    let field = &records [0].fields [0];
+    assert_eq!(field.id, "00_0010_4221_10_0100_000_2_0");
+    assert_eq!(field.name, "Temperature sensor 1");
+    assert_eq!(field.unit, "°C");
+    assert_eq!(field.value, FieldValue::Real(22.6));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn describes_columns_in_fields_json() {
+    let dir = test_dir("fields-json");
+    write_vbus_file(&dir, "20240401", &every_ten_minutes("20240401"));
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        fields_json: true,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let header = fs::read_to_string(dir.join("20240401.csv")).unwrap().lines().next().unwrap().split('\t').map(str::to_string).collect::<Vec<_>>();
+    let fields: Vec<serde_json::Value> = serde_json::from_str(&fs::read_to_string(dir.join("20240401.fields.json")).unwrap()).unwrap();
+    assert_eq!(fields.len(), header.len() - 1);
+    assert_eq!(fields [0] ["index"], 1);
+    assert_eq!(fields [0] ["column"], header [1]);
+    assert_eq!(fields [0] ["name"], "Temperature sensor 1");
+    assert_eq!(fields [0] ["unit"], "°C");
+    assert_eq!(fields [0] ["source_address"], "4221");
+    assert_eq!(fields [0] ["destination_address"], "0010");
+    assert_eq!(fields [0] ["packet_field_id"], "00_0010_4221_10_0100_000_2_0");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn merges_date_range_into_single_file() {
+    let dir = test_dir("merge");
+    write_vbus_files(&dir, &["20240330", "20240331", "20240401", "20240402"]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        date_range: DateRange {
+            from: chrono::NaiveDate::from_ymd_opt(2024, 3, 31),
+            to: chrono::NaiveDate::from_ymd_opt(2024, 4, 1),
+        },
+        merge: true,
+        ..ConvertOptions::default()
+    };
+    let spec = SpecSource::embedded(Language::En);
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted, [dir.join("20240331-20240401.csv")]);
+
+    let timestamps = csv_file_timestamps(&dir.join("20240331-20240401.csv"));
+    assert_eq!(timestamps.len(), 2 * 24 * 6);
+    assert_eq!(timestamps [0], "31.03.2024 00:00:00");
+    assert_eq!(timestamps [timestamps.len() - 1], "01.04.2024 23:50:00");
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted.len(), 0);
+    assert_eq!(report.up_to_date, 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_timestamps_in_given_format() {
+    let dir = test_dir("timestamp-format");
+    write_vbus_files(&dir, &["20240401"]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        timestamp_format: "%Y-%m-%dT%H:%M:%S%z".to_string(),
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let timestamps = csv_file_timestamps(&dir.join("20240401.csv"));
+    assert_eq!(timestamps [0], "2024-04-01T00:00:00+0000");
+
+    let options = ConvertOptions {
+        timestamp_format: "%Y-%Q".to_string(),
+        ..options
+    };
+    assert!(convert(&dir, &SpecSource::embedded(Language::En), &options).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skips_constant_columns() {
+    let dir = test_dir("skip-constant");
+    let data = every_ten_minutes("20240401").into_iter().enumerate()
+        .map(|(index, timestamp)| packet_with_temperature(timestamp, 200 + index as i16))
+        .collect::<Vec<_>>();
+    write_vbus_file_with_data(&dir, "20240401", &data);
+    write_vbus_files(&dir, &["20240402"]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        skip_constant: true,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let contents = fs::read_to_string(dir.join("20240401.csv")).unwrap();
+    let lines = contents.lines().map(|line| line.split('\t').collect::<Vec<_>>()).collect::<Vec<_>>();
+    assert_eq!(lines [0], ["Date", "Temperature sensor 1 [°C]"]);
+    assert_eq!(lines [1], ["01.04.2024 00:00:00", "20.0"]);
+
+    let contents = fs::read_to_string(dir.join("20240402.csv")).unwrap();
+    let lines = contents.lines().map(|line| line.split('\t').collect::<Vec<_>>()).collect::<Vec<_>>();
+    assert_eq!(lines [0], ["Date"]);
+    assert_eq!(lines.len(), 1 + 24 * 6);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_byte_order_mark() {
+    let dir = test_dir("bom");
+    write_vbus_files(&dir, &["20240401"]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        bom: true,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let contents = fs::read(dir.join("20240401.csv")).unwrap();
+    assert!(contents.starts_with(b"\xEF\xBB\xBFDate\t"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_files_named_after_pattern() {
+    let dir = test_dir("filename-pattern");
+    write_vbus_files(&dir, &["20240331", "20240401"]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        filename_pattern: "{yyyy}/{mm}/{host}_{dd}".parse().unwrap(),
+        ..ConvertOptions::default()
+    };
+    let spec = SpecSource::embedded(Language::En);
+
+    let host = dir.file_name().unwrap().to_string_lossy().to_string();
+    let filename = dir.join("2024").join("04").join(format!("{}_01.csv", host));
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted.len(), 2);
+    assert_eq!(report.converted [1], filename);
+    assert_eq!(csv_file_timestamps(&filename) [0], "01.04.2024 00:00:00");
+
+    let report = convert(&dir, &spec, &options).unwrap();
+    assert_eq!(report.converted.len(), 0);
+    assert_eq!(report.up_to_date, 2);
+
+    assert!("{yyyy}/{mm}".parse::<FilenamePattern>().is_err());
+    assert!("{date}_{minute}".parse::<FilenamePattern>().is_err());
+    assert!("../{date}".parse::<FilenamePattern>().is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_nothing_during_dry_run() {
+    let dir = test_dir("dry-run");
+    write_vbus_files(&dir, &["20240401"]);
+
+    for output_format in [OutputFormat::Csv, OutputFormat::Sqlite] {
+        let options = ConvertOptions {
+            timezone: chrono_tz::UTC,
+            output_format,
+            dry_run: true,
+            ..ConvertOptions::default()
+        };
+        let report = convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+        assert_eq!(report.converted.len(), 0);
+    }
+
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_gaps_between_data_sets() {
+    let dir = test_dir("gaps");
+    let timestamps = every_ten_minutes("20240401").into_iter()
+        .filter(|timestamp| timestamp.format("%H").to_string() != "12")
+        .collect::<Vec<_>>();
+    write_vbus_file(&dir, "20240401", &timestamps);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        gap_threshold: Some(std::time::Duration::from_secs(15 * 60)),
+        gaps_json: true,
+        ..ConvertOptions::default()
+    };
+    let report = convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    assert_eq!(report.gaps, [Gap {
+        start: Utc.with_ymd_and_hms(2024, 4, 1, 11, 50, 0).unwrap(),
+        end: Utc.with_ymd_and_hms(2024, 4, 1, 13, 0, 0).unwrap(),
+    }]);
+    assert_eq!(report.gaps [0].duration(), Duration::minutes(70));
+
+    let gaps: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("20240401_gaps.json")).unwrap()).unwrap();
+    assert_eq!(gaps, serde_json::json!([{
+        "start": "2024-04-01T11:50:00+00:00",
+        "end": "2024-04-01T13:00:00+00:00",
+        "duration": 4200,
+    }]));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_given_columns_in_order() {
+    let dir = test_dir("columns");
+    write_vbus_files(&dir, &["20240401"]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        columns: vec!["temperature sensor 2".to_string(), "Temperature sensor 1".to_string(), "Flow rate".to_string(), "No such field".to_string()],
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let contents = fs::read_to_string(dir.join("20240401.csv")).unwrap();
+    let lines = contents.lines().map(|line| line.split('\t').collect::<Vec<_>>()).collect::<Vec<_>>();
+    assert_eq!(lines [0], ["Date", "Temperature sensor 2 [°C]", "Temperature sensor 1 [°C]", "Flow rate [m³/h]"]);
+    assert_eq!(lines [1], ["01.04.2024 00:00:00", "0.0", "22.6", ""]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_display_names_of_columns() {
+    let dir = test_dir("column-names");
+    write_vbus_files(&dir, &["20240401"]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        column_names: ColumnNames::parse(r#"{"temperature sensor 2": "tank_temperature"}"#).unwrap(),
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let contents = fs::read_to_string(dir.join("20240401.csv")).unwrap();
+    let header = contents.lines().next().unwrap().split('\t').collect::<Vec<_>>();
+    assert_eq!(header [0..4], ["Date", "Temperature sensor 1 [°C]", "tank_temperature", "Temperature sensor 3 [°C]"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_localized_or_given_timestamp_column_header() {
+    let dir = test_dir("timestamp-column");
+    write_vbus_files(&dir, &["20240401"]);
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::De), &options).unwrap();
+
+    let contents = fs::read_to_string(dir.join("20240401.csv")).unwrap();
+    assert!(contents.starts_with("Datum\tTemperatur Sensor 1 [°C]\t"));
+
+    let options = ConvertOptions {
+        timestamp_column: Some("timestamp".to_string()),
+        ..options
+    };
+    fs::remove_file(dir.join("20240401.csv")).unwrap();
+    convert(&dir, &SpecSource::embedded(Language::De), &options).unwrap();
+
+    let contents = fs::read_to_string(dir.join("20240401.csv")).unwrap();
+    assert!(contents.starts_with("timestamp\tTemperatur Sensor 1 [°C]\t"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shows_source_addresses() {
+    let dir = test_dir("source-addresses");
+    write_vbus_files(&dir, &["20240401"]);
+
+    let mut options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        source_addresses: SourceAddresses::Headers,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let contents = fs::read_to_string(dir.join("20240401.csv")).unwrap();
+    let header = contents.lines().next().unwrap().split('\t').collect::<Vec<_>>();
+    assert_eq!(header [0..2], ["Date", "4221: Temperature sensor 1 [°C]"]);
+
+    fs::remove_file(dir.join("20240401.csv")).unwrap();
+    options.source_addresses = SourceAddresses::Columns;
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+
+    let contents = fs::read_to_string(dir.join("20240401.csv")).unwrap();
+    let lines = contents.lines().map(|line| line.split('\t').collect::<Vec<_>>()).collect::<Vec<_>>();
+    assert_eq!(lines [0] [0..3], ["Date", "DeltaSol BS Plus: Source address", "Temperature sensor 1 [°C]"]);
+    assert_eq!(lines [1] [0..3], ["01.04.2024 00:00:00", "4221", "22.6"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn converts_stream_within_date_range() {
+    let dir = test_dir("stream");
+    write_vbus_files(&dir, &["20240401", "20240402"]);
+    let mut bytes = fs::read(dir.join("20240401.vbus")).unwrap();
+    bytes.extend(fs::read(dir.join("20240402.vbus")).unwrap());
+
+    let options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        date_range: DateRange {
+            from: Some(chrono::NaiveDate::from_ymd_opt(2024, 4, 2).unwrap()),
+            to: None,
+        },
+        ..ConvertOptions::default()
+    };
+    let output = fs::File::create(dir.join("output.csv")).unwrap();
+    assert!(convert_stream(&mut &bytes [..], Box::new(output), &SpecSource::embedded(Language::En), &options).unwrap());
+
+    let timestamps = csv_file_timestamps(&dir.join("output.csv"));
+    assert_eq!(timestamps.len(), 24 * 6);
+    assert_eq!(timestamps [0], "02.04.2024 00:00:00");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn converts_directory_to_stream_without_files() {
+    let dir = test_dir("dir-stream");
+    write_vbus_files(&dir, &["20240401", "20240402", "20240403"]);
+
+    let day = chrono::NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+    let options = ConvertOptions {
+        date_range: DateRange {
+            from: Some(day),
+            to: Some(day),
+        },
+        ..ConvertOptions::default()
+    };
+    let output_filename = std::env::temp_dir().join(format!("vbus-sync-test-dir-stream-{}.csv", std::process::id()));
+    let output = fs::File::create(&output_filename).unwrap();
+    assert!(convert_dir_to_stream(&dir, Box::new(output), &SpecSource::embedded(Language::En), &options).unwrap());
+
+    // The day starts during the UTC day before in Berlin
+    let timestamps = csv_file_timestamps(&output_filename);
+    assert_eq!(timestamps.len(), 24 * 6);
+    assert_eq!(timestamps [0], "02.04.2024 00:00:00");
+    assert!(!dir.join("20240402.csv").exists());
+
+    fs::remove_file(&output_filename).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn drops_or_sorts_data_sets_out_of_order() {
+    let dir = test_dir("out-of-order");
+    let mut timestamps = every_ten_minutes("20240401");
+    timestamps.swap(1, 2);
+    write_vbus_file(&dir, "20240401", &timestamps);
+
+    let mut options = ConvertOptions {
+        timezone: chrono_tz::UTC,
+        out_of_order: OutOfOrder::Drop,
+        ..ConvertOptions::default()
+    };
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+    assert_eq!(csv_timestamps(&dir) [0..3], ["01.04.2024 00:00:00", "01.04.2024 00:20:00", "01.04.2024 00:30:00"]);
+
+    fs::remove_file(dir.join("20240401.csv")).unwrap();
+    options.out_of_order = OutOfOrder::Sort;
+    convert(&dir, &SpecSource::embedded(Language::En), &options).unwrap();
+    assert_eq!(csv_timestamps(&dir) [0..3], ["01.04.2024 00:00:00", "01.04.2024 00:10:00", "01.04.2024 00:20:00"]);
+    assert_eq!(csv_timestamps(&dir).len(), 24 * 6);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+use std::{fs, path::PathBuf};
+
+use chrono::{DateTime, TimeZone, Utc};
+use resol_vbus::{Data, DataSet, Header, Packet, RecordingWriter};
+use vbus_sync::dump_raw;
+
+/// Create an empty directory for a test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vbus-sync-test-dump-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The recorded bytes of a data set holding a single packet.
+fn recorded_packet(timestamp: DateTime<Utc>) -> Vec<u8> {
+    let mut frame_data = [0u8; 508];
+    frame_data [0..4].copy_from_slice(&[0xE2, 0x00, 0x81, 0x01]);
+    let mut data_set = DataSet::new();
+    data_set.timestamp = timestamp;
+    data_set.add_data(Data::Packet(Packet {
+        header: Header {
+            timestamp,
+            channel: 1,
+            destination_address: 0x0010,
+            source_address: 0x4221,
+            protocol_version: 0x10,
+        },
+        command: 0x0100,
+        frame_count: 1,
+        frame_data,
+    }));
+    let mut bytes = Vec::new();
+    RecordingWriter::new(&mut bytes).write_data_set(&data_set).unwrap();
+    bytes
+}
+
+#[test]
+fn dumps_packets_as_hex() {
+    let dir = test_dir("hex");
+
+    let timestamp = Utc.with_ymd_and_hms(2024, 4, 1, 12, 0, 0).unwrap();
+    let filename = dir.join("20240401.vbus");
+    fs::write(&filename, recorded_packet(timestamp)).unwrap();
+
+    let mut output = Vec::new();
+    assert_eq!(dump_raw(&[filename], &mut output).unwrap(), 1);
+    assert_eq!(String::from_utf8(output).unwrap(), "2024-04-01T12:00:00+00:00\t1\tAA10002142100001017A620001010516\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skips_corrupt_records() {
+    let dir = test_dir("corrupt");
+
+    let timestamp = Utc.with_ymd_and_hms(2024, 4, 1, 12, 0, 0).unwrap();
+    let mut corrupt = recorded_packet(timestamp);
+    // A negative timestamp, which `RecordingReader` panics on
+    corrupt [6..14].fill(0xFF);
+    let bytes = [recorded_packet(timestamp), corrupt, recorded_packet(timestamp + chrono::Duration::minutes(1))].concat();
+    let filename = dir.join("20240401.vbus");
+    fs::write(&filename, bytes).unwrap();
+
+    let mut output = Vec::new();
+    assert_eq!(dump_raw(&[filename], &mut output).unwrap(), 2);
+    assert_eq!(String::from_utf8(output).unwrap().lines().map(|line| line.split('\t').next().unwrap()).collect::<Vec<_>>(), ["2024-04-01T12:00:00+00:00", "2024-04-01T12:01:00+00:00"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,82 @@
+use vbus_sync::{parse_log_index, parse_next_page_link, LogFileEntry};
+
+fn entry(datecode: &str, size: Option<u64>) -> LogFileEntry {
+    LogFileEntry {
+        datecode: datecode.to_string(),
+        size,
+    }
+}
+
+#[test]
+fn parses_absolute_links() {
+    let html = include_str!("fixtures/log_index_absolute.html");
+
+    assert_eq!(parse_log_index(html), [
+        entry("20240330", Some(13392)),
+        entry("20240331", Some(13392)),
+        entry("20240401", Some(4128)),
+    ]);
+}
+
+#[test]
+fn parses_relative_links() {
+    let html = include_str!("fixtures/log_index_relative.html");
+
+    assert_eq!(parse_log_index(html), [
+        entry("20241026", Some(13392)),
+        entry("20241027", Some(13392)),
+        entry("20241028", Some(13392)),
+    ]);
+}
+
+#[test]
+fn parses_links_without_sizes() {
+    let html = include_str!("fixtures/log_index_preformatted.html");
+
+    assert_eq!(parse_log_index(html), [
+        entry("20240101", None),
+        entry("20240102", Some(13392)),
+        entry("20240103", Some(13392)),
+        entry("20240104", None),
+    ]);
+}
+
+#[test]
+fn ignores_truncated_index() {
+    let html = include_str!("fixtures/log_index_relative.html");
+
+    for len in 0..html.len() {
+        if html.is_char_boundary(len) {
+            let entries = parse_log_index(&html [..len]);
+            assert!(entries.len() <= 3);
+            assert!(entries.iter().all(|entry| entry.datecode.len() == 8));
+        }
+    }
+}
+
+#[test]
+fn ignores_malformed_links() {
+    let html = "<a href=\"2024010_packets.vbus\"></a><a href=\"2024x101_packets.vbus\"></a><a href=\"20240101_packets.vbus\"></a><a href=\"20240101_packets.vbus\"></a><a href=";
+
+    let datecodes = parse_log_index(html).into_iter().map(|entry| entry.datecode).collect::<Vec<_>>();
+    assert_eq!(datecodes, ["20240101"]);
+}
+
+#[test]
+fn finds_link_to_next_page() {
+    let html = "<a href=\"20240101_packets.vbus\">20240101_packets.vbus</a><a href=\"?page=1\">Previous</a><a href=\"?page=3\" rel=\"next\">3</a>";
+    assert_eq!(parse_next_page_link(html).as_deref(), Some("?page=3"));
+
+    let html = "<a href=\"/log/?page=1\">&laquo;</a> <a href=\"/log/?page=3\"> Weiter &raquo; </a>";
+    assert_eq!(parse_next_page_link(html).as_deref(), Some("/log/?page=3"));
+
+    let html = "<a href=\"/log/?page=1\">&lt;&lt;</a> <a href=\"/log/?page=3\">&gt;&gt;</a>";
+    assert_eq!(parse_next_page_link(html).as_deref(), Some("/log/?page=3"));
+}
+
+#[test]
+fn finds_no_link_to_next_page_in_single_page() {
+    let html = include_str!("fixtures/log_index_relative.html");
+
+    assert_eq!(parse_next_page_link(html), None);
+}
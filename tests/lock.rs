@@ -0,0 +1,19 @@
+use std::fs;
+
+use vbus_sync::DirLock;
+
+#[test]
+fn locks_directory_until_dropped() {
+    let dir = std::env::temp_dir().join(format!("vbus-sync-test-lock-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let lock = DirLock::try_acquire(&dir).unwrap();
+    assert!(lock.is_some());
+    assert!(dir.is_dir());
+    assert!(DirLock::try_acquire(&dir).unwrap().is_none());
+
+    drop(lock);
+    assert!(DirLock::try_acquire(&dir).unwrap().is_some());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,28 @@
+use std::fs;
+
+use vbus_sync::write_manifest;
+
+#[test]
+fn lists_vbus_files() {
+    let dir = std::env::temp_dir().join(format!("vbus-sync-test-manifest-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(dir.join("20240402.vbus"), b"abc").unwrap();
+    fs::write(dir.join("20240401.vbus"), b"").unwrap();
+    fs::write(dir.join("20240401.vbus.sha256"), b"").unwrap();
+    fs::write(dir.join("20240401.csv"), b"").unwrap();
+
+    let filename = write_manifest(&dir).unwrap();
+    assert_eq!(filename, dir.join("manifest.json"));
+
+    let manifest: serde_json::Value = serde_json::from_slice(&fs::read(filename).unwrap()).unwrap();
+    let files = manifest ["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files [0]["datecode"], "20240401");
+    assert_eq!(files [0]["size"], 0);
+    assert_eq!(files [1]["datecode"], "20240402");
+    assert_eq!(files [1]["filename"], "20240402.vbus");
+    assert_eq!(files [1]["size"], 3);
+    assert!(files [1]["modified"].as_str().unwrap().ends_with("+00:00"));
+}
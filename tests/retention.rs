@@ -0,0 +1,79 @@
+use std::fs;
+
+use chrono::NaiveDate;
+use vbus_sync::{remove_old_files, FilenamePattern};
+
+#[test]
+fn removes_files_of_days_before_cutoff() {
+    let dir = std::env::temp_dir().join(format!("vbus-sync-test-retention-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let filenames = [
+        "20240330.vbus",
+        "20240330.vbus.sha256",
+        "20240330.csv",
+        "20240331.vbus",
+        "20240331.vbus.meta",
+        "20240331_summary.csv",
+        "20240401.vbus",
+        "20240401.csv",
+        "20241399.vbus",
+        "log-index.json",
+        "notes.txt",
+    ];
+    for filename in filenames {
+        fs::write(dir.join(filename), b"").unwrap();
+    }
+    let cutoff = NaiveDate::from_ymd_opt(2024, 4, 1).unwrap();
+
+    let removed = remove_old_files(&dir, cutoff, None, true).unwrap();
+    assert_eq!(removed, ["20240330.vbus", "20240330.vbus.sha256", "20240331.vbus", "20240331.vbus.meta"].map(|filename| dir.join(filename)));
+    assert!(filenames.iter().all(|filename| dir.join(filename).exists()));
+
+    remove_old_files(&dir, cutoff, None, false).unwrap();
+    let removed = remove_old_files(&dir, cutoff, Some(&FilenamePattern::default()), false).unwrap();
+    assert_eq!(removed, ["20240330.csv", "20240331_summary.csv"].map(|filename| dir.join(filename)));
+
+    let mut remaining = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect::<Vec<_>>();
+    remaining.sort();
+    assert_eq!(remaining, ["20240401.csv", "20240401.vbus", "20241399.vbus", "log-index.json", "notes.txt"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn removes_output_files_in_any_format_named_by_pattern() {
+    let dir = std::env::temp_dir().join(format!("vbus-sync-test-retention-pattern-{}", std::process::id())).join("192.168.1.50");
+    let _ = fs::remove_dir_all(&dir);
+
+    let filenames = [
+        "2024/03/192.168.1.50_31.parquet",
+        "2024/03/192.168.1.50_31.fields.json",
+        "2024/03/192.168.1.50_30.jsonl.gz",
+        "2024/03/192.168.1.50_30_gaps.json",
+        "2024/03/192.168.1.50_29.lp",
+        "2024/03/192.168.1.50_29_summary.csv",
+        "2024/03/other_28.lp",
+        "2024/03/192.168.1.50_28.txt",
+        "2024/04/192.168.1.50_01.parquet",
+        "20240330.jsonl",
+    ];
+    for filename in filenames {
+        let filename = dir.join(filename);
+        fs::create_dir_all(filename.parent().unwrap()).unwrap();
+        fs::write(filename, b"").unwrap();
+    }
+    let cutoff = NaiveDate::from_ymd_opt(2024, 4, 1).unwrap();
+    let pattern = "{yyyy}/{mm}/{host}_{dd}".parse::<FilenamePattern>().unwrap();
+
+    assert!(remove_old_files(&dir, cutoff, None, false).unwrap().is_empty());
+
+    let removed = remove_old_files(&dir, cutoff, Some(&pattern), false).unwrap();
+    let mut expected = filenames [..6].iter().map(|filename| dir.join(filename)).collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(removed, expected);
+    assert!(filenames [6..].iter().all(|filename| dir.join(filename).exists()));
+
+    fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,154 @@
+use std::{collections::BTreeMap, fs, io::{BufRead, BufReader, Write}, net::{TcpListener, TcpStream}, path::{Path, PathBuf}, sync::{Arc, Mutex}, thread};
+
+use vbus_sync::{sync, Host, SyncOptions, SyncReport};
+
+/// Create an empty directory for a test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vbus-sync-test-sync-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// The log files served by a `TestDevice` and the requests it received.
+#[derive(Default)]
+struct DeviceState {
+    files: BTreeMap<String, Vec<u8>>,
+    honors_conditions: bool,
+    requests: Vec<String>,
+}
+
+/// A web interface serving an index of log files with their sizes and an `ETag` for each one.
+struct TestDevice {
+    addr: String,
+    state: Arc<Mutex<DeviceState>>,
+}
+
+impl TestDevice {
+    fn start(honors_conditions: bool) -> TestDevice {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let state = Arc::new(Mutex::new(DeviceState {
+            honors_conditions,
+            ..DeviceState::default()
+        }));
+
+        let device_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let state = device_state.clone();
+                thread::spawn(move || serve_connection(stream.unwrap(), &state));
+            }
+        });
+
+        TestDevice { addr, state }
+    }
+
+    fn set_file(&self, datecode: &str, contents: &[u8]) {
+        self.state.lock().unwrap().files.insert(datecode.to_string(), contents.to_vec());
+    }
+
+    /// The log files requested since the last call.
+    fn take_file_requests(&self) -> Vec<String> {
+        let requests = std::mem::take(&mut self.state.lock().unwrap().requests);
+        requests.into_iter().filter(|path| path.ends_with("_packets.vbus")).collect()
+    }
+
+    fn sync(&self, dir: &Path) -> SyncReport {
+        let options = SyncOptions::default();
+        let host = Host::parse(&self.addr, &options).unwrap();
+        async_std::task::block_on(sync(&host, dir, &options)).unwrap()
+    }
+}
+
+/// Answer the requests sent over a connection until it is closed.
+fn serve_connection(stream: TcpStream, state: &Mutex<DeviceState>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+
+        let mut headers = BTreeMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(':') {
+                Some((name, value)) => headers.insert(name.to_ascii_lowercase(), value.trim().to_string()),
+                None => break,
+            };
+        }
+
+        let mut state = state.lock().unwrap();
+        state.requests.push(path.clone());
+
+        let (status, mut extra_headers, body) = if path == "/log/" {
+            let links = state.files.iter().map(|(datecode, contents)| {
+                format!("<li><a href=\"{0}_packets.vbus\">{0}_packets.vbus</a> ({1} bytes)</li>\n", datecode, contents.len())
+            }).collect::<String>();
+            ("200 OK", Vec::new(), format!("<html><body><ul>\n{}</ul></body></html>", links).into_bytes())
+        } else {
+            let datecode = path.trim_start_matches("/log/").trim_end_matches("_packets.vbus");
+            let contents = state.files [datecode].clone();
+            let etag = format!("\"{}\"", contents.len());
+            let range_start = headers.get("range").and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+            if state.honors_conditions && headers.get("if-none-match") == Some(&etag) {
+                ("304 Not Modified", vec![format!("ETag: {}", etag)], Vec::new())
+            } else if let Some(start) = range_start {
+                let content_range = format!("Content-Range: bytes {}-{}/{}", start, contents.len() - 1, contents.len());
+                ("206 Partial Content", vec![format!("ETag: {}", etag), content_range], contents [start..].to_vec())
+            } else {
+                ("200 OK", vec![format!("ETag: {}", etag)], contents)
+            }
+        };
+        drop(state);
+
+        extra_headers.push(format!("Content-Length: {}", body.len()));
+        let head = format!("HTTP/1.1 {}\r\n{}\r\n\r\n", status, extra_headers.join("\r\n"));
+        if writer.write_all(head.as_bytes()).and_then(|()| writer.write_all(&body)).is_err() {
+            return;
+        }
+    }
+}
+
+#[test]
+fn skips_log_files_listed_with_same_size_while_newest_grows() {
+    let dir = test_dir("growing");
+    let device = TestDevice::start(true);
+    device.set_file("20240101", b"first day");
+    device.set_file("20240102", b"second day");
+    device.set_file("20240103", b"third");
+
+    let report = device.sync(&dir);
+    assert_eq!(report.downloaded, ["20240101", "20240102", "20240103"]);
+    device.take_file_requests();
+
+    device.set_file("20240103", b"third day");
+    let report = device.sync(&dir);
+    assert_eq!(report.downloaded, ["20240103"]);
+    assert_eq!(device.take_file_requests(), ["/log/20240103_packets.vbus"]);
+    assert_eq!(fs::read(dir.join("20240103.vbus")).unwrap(), b"third day");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keeps_unchanged_log_files_if_device_ignores_conditions() {
+    let dir = test_dir("ignoring");
+    let device = TestDevice::start(false);
+    device.set_file("20240101", b"first day");
+    device.set_file("20240102", b"second day");
+
+    let report = device.sync(&dir);
+    assert_eq!(report.downloaded, ["20240101", "20240102"]);
+    let modified = fs::metadata(dir.join("20240102.vbus")).unwrap().modified().unwrap();
+
+    thread::sleep(std::time::Duration::from_millis(20));
+    let report = device.sync(&dir);
+    assert!(report.downloaded.is_empty());
+    assert_eq!(fs::metadata(dir.join("20240102.vbus")).unwrap().modified().unwrap(), modified);
+
+    fs::remove_dir_all(&dir).unwrap();
+}